@@ -0,0 +1,75 @@
+//! Relative checks with and without the cached instruction fingerprint.
+//! Like `map_storage`, this is a plain program timing with `Instant`.
+//! Run with `cargo bench --bench kin_check`
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rand::prelude::*;
+
+use cell_simulation::simulation::bot::Bot;
+use cell_simulation::simulation::config::KinCompareMode;
+use cell_simulation::Config;
+
+const BOTS: usize = 1000;
+const ROUNDS: u32 = 20;
+
+fn main() {
+    let config = Config::default();
+    let mut rng = StdRng::seed_from_u64(0);
+
+    // Every bot has the same genome, so fingerprints always match
+    let ancestor = Bot::new_random_with_rng(&mut rng, 0, 0, &config);
+    let monoculture: Vec<Bot> = (0..BOTS)
+        .map(|x| Bot::new_with_genome_and_rng(&mut rng, x, 0, ancestor.genome, &config))
+        .collect();
+    // Every bot differs from the ancestor by a single mutated gene,
+    // like a population that recently shared an ancestor
+    let mutants: Vec<Bot> = (0..BOTS)
+        .map(|x| {
+            let mut genome = ancestor.genome;
+            genome[rng.gen_range(0..genome.len())].mutate_with_rng(&mut rng, &config);
+            Bot::new_with_genome_and_rng(&mut rng, x, 0, genome, &config)
+        })
+        .collect();
+    // Every bot has it's own genome, so fingerprints almost never match
+    let diverse: Vec<Bot> = (0..BOTS)
+        .map(|x| Bot::new_random_with_rng(&mut rng, x, 0, &config))
+        .collect();
+
+    let populations = [
+        ("monoculture", &monoculture),
+        ("mutants", &mutants),
+        ("diverse", &diverse),
+    ];
+    for (name, bots) in populations {
+        let before = measure(bots, compare_genomes);
+        let after = measure(bots, |a, b| {
+            a.is_relative(b, KinCompareMode::InstructionsOnly)
+        });
+        println!("{name:>12}: {before:>12?} without fingerprint, {after:>12?} with fingerprint");
+    }
+}
+
+// How relatives were checked before the fingerprint was cached
+fn compare_genomes(a: &Bot, b: &Bot) -> bool {
+    a.genome
+        .iter()
+        .zip(b.genome.iter())
+        .all(|(ours, theirs)| ours.instruction == theirs.instruction)
+}
+
+// Average time of checking every pair of bots
+fn measure(bots: &[Bot], is_relative: impl Fn(&Bot, &Bot) -> bool) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let mut relatives = 0usize;
+        for a in bots {
+            for b in bots {
+                relatives += is_relative(black_box(a), black_box(b)) as usize;
+            }
+        }
+        black_box(relatives);
+    }
+    start.elapsed() / ROUNDS
+}
@@ -0,0 +1,59 @@
+//! Dense vs sparse map storage on big, mostly empty maps.
+//! There is no benchmark framework among the dependencies, so this is a plain program
+//! timing the simulation with `Instant`. Run with `cargo bench --bench map_storage`
+
+use std::time::{Duration, Instant};
+
+use cell_simulation::simulation::bot::Bot;
+use cell_simulation::simulation::config::SeedingPattern;
+use cell_simulation::simulation::Simulation;
+use cell_simulation::Config;
+
+const UPDATES: u32 = 20;
+// One bot per this many cells
+const OCCUPANCY: usize = 1000;
+
+fn main() {
+    println!(
+        "{} bytes per stored cell, 1 bot per {OCCUPANCY} cells",
+        std::mem::size_of::<Bot>()
+    );
+
+    for size in [250, 500, 1000, 2000] {
+        // A dense 2000x2000 map takes over a gigabyte, the sparse one is what makes it possible
+        let storages: &[bool] = if size > 1000 { &[true] } else { &[false, true] };
+        for &sparse_map in storages {
+            let (update, clone) = measure(size, sparse_map);
+            println!(
+                "{size}x{size} {:>6}: {update:>12?} per update, {clone:>12?} per map clone",
+                if sparse_map { "sparse" } else { "dense" }
+            );
+        }
+    }
+}
+
+// Average time of an update, and time of cloning the map, like the runner does for metadata
+fn measure(size: usize, sparse_map: bool) -> (Duration, Duration) {
+    let config = Config {
+        width: size,
+        height: size,
+        sparse_map,
+        seeding_pattern: SeedingPattern::SingleBot,
+        ..Default::default()
+    };
+    let mut simulation = Simulation::new_seeded(Some(config), 0);
+    simulation.inject_random(size * size / OCCUPANCY);
+
+    let start = Instant::now();
+    for _ in 0..UPDATES {
+        simulation.update();
+    }
+    let update = start.elapsed() / UPDATES;
+
+    let start = Instant::now();
+    let copy = simulation.map().clone();
+    let clone = start.elapsed();
+    drop(copy);
+
+    (update, clone)
+}
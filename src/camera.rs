@@ -0,0 +1,140 @@
+use macroquad::prelude::*;
+
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 16.0;
+// How much one step of the mouse wheel changes the zoom
+const ZOOM_STEP: f32 = 1.1;
+// Fraction of the distance to the target covered each frame, when following something
+const FOLLOW_SMOOTHING: f32 = 0.1;
+
+/// Pans and zooms the view of the grid.
+/// Mouse wheel zooms, dragging with the middle mouse button pans
+pub struct Camera {
+    // Point of the grid (in pixels) shown in the center of the screen
+    center: Vec2,
+    zoom: f32,
+
+    // Where the camera is heading to, if it is following something
+    target: Option<Vec2>,
+    // Mouse position on the previous frame, while panning
+    drag_origin: Option<Vec2>,
+}
+
+impl Camera {
+    /// Camera showing the whole grid of given size, like it was drawn without a camera
+    pub fn new(grid_width: f32, grid_height: f32) -> Self {
+        Camera {
+            center: vec2(grid_width / 2., grid_height / 2.),
+            zoom: 1.0,
+            target: None,
+            drag_origin: None,
+        }
+    }
+
+    /// Smoothly move the camera towards a point of the grid
+    pub fn follow(&mut self, x: f32, y: f32) {
+        self.target = Some(vec2(x, y));
+    }
+    pub fn stop_following(&mut self) {
+        self.target = None;
+    }
+
+    /// Zoom and pan with the mouse, and move towards the followed point
+    pub fn update(&mut self, handle_mouse: bool) {
+        if handle_mouse {
+            let (_, wheel) = mouse_wheel();
+            if wheel > 0. {
+                self.zoom = (self.zoom * ZOOM_STEP).min(MAX_ZOOM);
+            } else if wheel < 0. {
+                self.zoom = (self.zoom / ZOOM_STEP).max(MIN_ZOOM);
+            }
+        }
+
+        let mouse = Vec2::from(mouse_position());
+        if is_mouse_button_down(MouseButton::Middle) && (handle_mouse || self.drag_origin.is_some())
+        {
+            if let Some(origin) = self.drag_origin {
+                self.center -= (mouse - origin) / self.zoom;
+                // Panning by hand overrides following
+                self.target = None;
+            }
+            self.drag_origin = Some(mouse);
+        } else {
+            self.drag_origin = None;
+        }
+
+        if let Some(target) = self.target {
+            self.center += (target - self.center) * FOLLOW_SMOOTHING;
+        }
+    }
+
+    /// Convert a point on the screen to a point of the grid
+    pub fn screen_to_grid(&self, x: f32, y: f32) -> (f32, f32) {
+        let point = (vec2(x, y) - screen_center()) / self.zoom + self.center;
+        (point.x, point.y)
+    }
+
+    /// Draw the texture of the grid as seen through the camera
+    pub fn draw_grid(&self, texture: Texture2D, grid_width: f32, grid_height: f32) {
+        let top_left = screen_center() - self.center * self.zoom;
+
+        draw_texture_ex(
+            texture,
+            top_left.x,
+            top_left.y,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(vec2(grid_width, grid_height) * self.zoom),
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Draw a dimmed copy of the opposite edges around the grid, `border` pixels wide,
+    /// on the axes that wrap around. Only the grid itself is clickable
+    pub fn draw_ghost_border(
+        &self,
+        texture: Texture2D,
+        grid_size: Vec2,
+        border: f32,
+        wrap_x: bool,
+        wrap_y: bool,
+    ) {
+        let top_left = screen_center() - self.center * self.zoom;
+
+        // For each axis: -1 is the ghost before the grid, 1 is the one after it
+        let offsets = |wrap: bool| if wrap { vec![-1, 0, 1] } else { vec![0] };
+        // Part of the texture on the opposite edge, and where it's drawn
+        let slice = |offset: i32, size: f32| match offset {
+            -1 => (size - border, border, -border),
+            1 => (0., border, size),
+            _ => (0., size, 0.),
+        };
+
+        for dx in offsets(wrap_x) {
+            for dy in offsets(wrap_y) {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+                let (source_x, width, x) = slice(dx, grid_size.x);
+                let (source_y, height, y) = slice(dy, grid_size.y);
+
+                draw_texture_ex(
+                    texture,
+                    top_left.x + x * self.zoom,
+                    top_left.y + y * self.zoom,
+                    Color::new(0.5, 0.5, 0.5, 1.),
+                    DrawTextureParams {
+                        source: Some(Rect::new(source_x, source_y, width, height)),
+                        dest_size: Some(vec2(width, height) * self.zoom),
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+    }
+}
+
+fn screen_center() -> Vec2 {
+    vec2(screen_width() / 2., screen_height() / 2.)
+}
@@ -0,0 +1,74 @@
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong when using the simulation through it's public API
+#[derive(Debug)]
+pub enum Error {
+    /// Simulation thread has stopped, so commands can't be sent to it
+    ChannelClosed,
+    /// Reading or writing a file failed
+    Io(io::Error),
+    /// A save file couldn't be parsed or written
+    Serde(serde_json::Error),
+    /// A save file was written by an incompatible version, see [`SAVE_VERSION`](crate::simulation::save::SAVE_VERSION)
+    SaveVersion(u32),
+    /// Config (or a state using it) was rejected, with the reason
+    InvalidConfig(String),
+    /// Coordinates are outside of the map
+    OutOfBounds(usize, usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ChannelClosed => write!(f, "simulation thread has stopped"),
+            Self::Io(error) => write!(f, "{error}"),
+            Self::Serde(error) => write!(f, "invalid save file: {error}"),
+            Self::SaveVersion(version) => write!(
+                f,
+                "save file has version {version}, but only version {} can be loaded",
+                crate::simulation::save::SAVE_VERSION
+            ),
+            Self::InvalidConfig(reason) => write!(f, "invalid config: {reason}"),
+            Self::OutOfBounds(x, y) => write!(f, "cell {x}, {y} is outside of the map"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::Serde(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Serde(error)
+    }
+}
+
+// The command itself is dropped, it can't be delivered anyway
+impl<T> From<std::sync::mpsc::SendError<T>> for Error {
+    fn from(_: std::sync::mpsc::SendError<T>) -> Self {
+        Self::ChannelClosed
+    }
+}
+
+// The simulation thread drops the reply sender only when it stops
+impl From<std::sync::mpsc::RecvError> for Error {
+    fn from(_: std::sync::mpsc::RecvError) -> Self {
+        Self::ChannelClosed
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,7 @@
+pub mod camera;
+pub mod error;
+pub mod renderer;
+pub mod runner;
+pub mod simulation;
+
+pub use simulation::config::{Config, GENOME_LENGTH};
@@ -1,16 +1,28 @@
-pub mod renderer;
-pub mod runner;
-pub mod simulation;
-
+use egui::plot::{Bar, BarChart, Line, Plot, PlotPoints};
+use egui::Color32;
 use egui::DragValue;
+use egui::RichText;
 use egui::Slider;
 use macroquad::prelude::*;
 
-use renderer::RenderingMode;
-use runner::SimulationRunner;
+use cell_simulation::{camera, error, renderer, runner, simulation};
+
+use camera::Camera;
+use error::Error;
+use renderer::{Palette, RenderingMode, DENSITY_WINDOW_RANGE};
+use runner::{SimulationRunner, StopCondition};
+use simulation::bot::Bot;
 use simulation::config::*;
+use simulation::direction::Direction;
+use simulation::gene;
+use simulation::gene::Instruction;
+use simulation::save;
+use simulation::stats::SpeciesDefinition;
 use simulation::Simulation;
 
+/// Points kept in the genome complexity plot, older ones are dropped
+const COMPLEXITY_HISTORY_LENGTH: usize = 2000;
+
 fn window_config() -> Conf {
     let default_config = Config::default();
 
@@ -22,37 +34,388 @@ fn window_config() -> Conf {
     }
 }
 
+// Whether a command failed because the simulation thread has stopped
+fn channel_closed(result: error::Result<()>) -> bool {
+    matches!(result, Err(Error::ChannelClosed))
+}
+
+// With the `image` feature, the map can be loaded from an image passed as the first argument
+fn initial_simulation() -> Simulation {
+    #[cfg(feature = "image")]
+    if let Some(path) = std::env::args().nth(1) {
+        match Simulation::from_image(&path, Config::default()) {
+            Ok(simulation) => return simulation,
+            Err(error) => eprintln!("Failed to load the map from {path}: {error}"),
+        }
+    }
+
+    Simulation::new(None)
+}
+
 #[macroquad::main(window_config)]
 async fn main() {
     // Start 4 simulations, each in it's own thread
-    let mut simulation = SimulationRunner::start_new(Simulation::new(None));
+    let mut simulation = SimulationRunner::start_new(initial_simulation());
     let mut rendering_mode = RenderingMode::Normal;
+    // When enabled, left mouse button places walls, and right mouse button erases cells
+    let mut paint_walls = false;
+    // When enabled, left mouse button plants bots with the imported genome
+    let mut plant_genome = false;
+    // When enabled, left mouse button stamps copies of the selected bot's genome
+    let mut stamp_selected = false;
+    // Status bar at the top of the screen, toggled with F1
+    let mut show_status_bar = true;
+    let mut genome_string = String::new();
+    // Result of the last config save or load
+    let mut config_message: Option<String> = None;
+
+    // Condition for the "Run until" button
+    let mut run_until = StopCondition::Extinction;
+    // How many random bots the "Inject" button places
+    let mut inject_count = 100;
+    // Copies of the imported genome placed at once
+    let mut invasion_count = 50;
+
+    // Directory for the time-lapse frames, applied when the text field loses focus
+    #[cfg(feature = "image")]
+    let mut timelapse_dir = String::from("timelapse");
+
+    // Reload config.json whenever it changes
+    #[cfg(feature = "watch-config")]
+    let mut watch_config = false;
+
+    // Limit on how often frames are drawn, regardless of the simulation speed
+    let mut fps_cap: Option<u32> = Some(60);
+    // Lower the TPS as the population grows, to keep the UI responsive
+    let mut adaptive_tps = false;
+
+    // The grid is drawn into a texture, and is redrawn only when something changes
+    let grid_config = *simulation.config();
+    let grid_width = (grid_config.width * grid_config.cell_size) as f32;
+    let grid_height = (grid_config.height * grid_config.cell_size) as f32;
+    let grid = render_target(grid_width as u32, grid_height as u32);
+    grid.texture.set_filter(FilterMode::Nearest);
+    let mut redraw_grid = true;
+    let mut drawn_rendering_mode = rendering_mode;
+    // Tint empty cells by the amount of light they get, instead of leaving them black
+    let mut light_background = false;
+    let mut drawn_light_background = light_background;
+    // Copies of the opposite edges drawn around the grid, on axes that wrap around
+    let mut ghost_border = false;
+    // Size of the square around each cell, counted in the Density rendering mode
+    let mut density_window = 5;
+    let mut palette = Palette::default();
+    // Result of the last palette load
+    let mut palette_message: Option<String> = None;
+    let mut drawn_density_window = density_window;
+    // Tint all the cells of the selected cell's lineage
+    let mut highlight_lineage = false;
+    let mut drawn_highlight_lineage = highlight_lineage;
+
+    let mut camera = Camera::new(grid_width, grid_height);
+    // Keep the most energetic cell in the center of the view
+    let mut follow_energetic = false;
+    // Keep the selected cell in the center of the view. Takes priority over the above
+    let mut follow_selected = false;
+    // Recentering is throttled, so that the camera doesn't jump between cells every frame
+    let mut last_recenter = 0.0;
+
+    // Set when a command couldn't be sent, because the simulation thread has stopped
+    let mut simulation_stopped = false;
+
+    // Iterations and average genome complexity, one point per new metadata
+    let mut complexity_history: Vec<[f64; 2]> = Vec::new();
 
     loop {
-        simulation.update();
+        let frame_start = get_time();
+        redraw_grid |= simulation.update();
+
+        let iterations = simulation.iterations() as f64;
+        match complexity_history.last() {
+            // Simulation was reset or loaded
+            Some(&[last, _]) if iterations < last => complexity_history.clear(),
+            Some(&[last, _]) if iterations == last => {}
+            _ => {
+                complexity_history.push([iterations, simulation.stats().average_complexity as f64]);
+                if complexity_history.len() > COMPLEXITY_HISTORY_LENGTH {
+                    complexity_history.remove(0);
+                }
+            }
+        }
 
         clear_background(BLACK);
 
+        if is_key_pressed(KeyCode::F1) {
+            show_status_bar = !show_status_bar;
+        }
+
+        let mut pointer_over_ui = false;
         egui_macroquad::ui(|ctx| {
+            pointer_over_ui = ctx.wants_pointer_input();
+
+            if simulation_stopped {
+                egui::TopBottomPanel::top("simulation_stopped").show(ctx, |ui| {
+                    ui.colored_label(
+                        Color32::RED,
+                        "Simulation thread has stopped, changes are no longer applied",
+                    );
+                });
+            }
+
+            if show_status_bar {
+                egui::TopBottomPanel::top("status_bar").show(ctx, |ui| {
+                    let stats = simulation.stats();
+                    ui.horizontal(|ui| {
+                        ui.label(format!("Alive: {}", stats.alive_count));
+                        ui.separator();
+                        ui.label(format!("Dead: {}", stats.dead_count));
+                        ui.separator();
+                        ui.label(format!("Species: {}", stats.species_count));
+                        ui.separator();
+                        ui.label(format!("Total energy: {:.0}", stats.total_energy));
+                        ui.separator();
+                        ui.label(format!("TPS: {}", simulation.tps()));
+                        ui.separator();
+                        ui.label(format!("Genome length: {GENOME_LENGTH}"));
+                    })
+                    .response
+                    .on_hover_text("Press F1 to hide");
+                });
+            }
+
             egui::Window::new("Simulation controls")
                 .resizable(false)
                 .show(ctx, |ui| {
                     ui.horizontal(|ui| {
-                        ui.button(if simulation.is_paused() {
+                        let pause_label = if simulation.is_paused() {
                             "Unpause"
                         } else {
                             "Pause"
-                        })
-                        .clicked()
-                        .then(|| simulation.toggle_pause());
+                        };
+                        if ui.button(pause_label).clicked() {
+                            simulation_stopped |= channel_closed(simulation.toggle_pause());
+                        }
 
-                        ui.button("Reset map").clicked().then(|| simulation.reset());
+                        if ui.button("Reset map").clicked() {
+                            simulation_stopped |= channel_closed(simulation.reset());
+                        }
+                        if ui
+                            .button("Reshuffle")
+                            .on_hover_text("Move all alive cells to random places, keeping genomes")
+                            .clicked()
+                        {
+                            simulation_stopped |= channel_closed(simulation.reshuffle());
+                        }
                     });
 
+                    ui.horizontal(|ui| {
+                        if ui.button("Run until").clicked() {
+                            simulation_stopped |= channel_closed(simulation.run_until(run_until));
+                        }
+
+                        egui::ComboBox::from_id_source("stop_condition")
+                            .selected_text(match run_until {
+                                StopCondition::Extinction => "extinction",
+                                StopCondition::PopulationBelow(_) => "population below",
+                                StopCondition::PopulationAbove(_) => "population above",
+                                StopCondition::SpeciesCountBelow(_) => "species below",
+                                StopCondition::Stagnation => "stagnation",
+                            })
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(
+                                    &mut run_until,
+                                    StopCondition::Extinction,
+                                    "extinction",
+                                );
+                                ui.selectable_value(
+                                    &mut run_until,
+                                    StopCondition::PopulationBelow(100),
+                                    "population below",
+                                );
+                                ui.selectable_value(
+                                    &mut run_until,
+                                    StopCondition::PopulationAbove(1000),
+                                    "population above",
+                                );
+                                ui.selectable_value(
+                                    &mut run_until,
+                                    StopCondition::SpeciesCountBelow(10),
+                                    "species below",
+                                );
+                                ui.selectable_value(
+                                    &mut run_until,
+                                    StopCondition::Stagnation,
+                                    "stagnation",
+                                );
+                            });
+
+                        match &mut run_until {
+                            StopCondition::Extinction | StopCondition::Stagnation => {}
+                            StopCondition::PopulationBelow(count)
+                            | StopCondition::PopulationAbove(count)
+                            | StopCondition::SpeciesCountBelow(count) => {
+                                ui.add(DragValue::new(count));
+                            }
+                        }
+                    });
+                    if let Some(condition) = simulation.stop_condition() {
+                        ui.label(format!("Running until {condition:?}"));
+                    }
+
                     ui.horizontal(|ui| {
                         ui.label(format!("TPS: {}", simulation.tps()));
                         ui.label(format!("Iterations: {}", simulation.iterations()))
                     });
+                    ui.horizontal(|ui| {
+                        ui.label(format!("Alive: {}", simulation.stats().alive_count));
+                        ui.label(format!("Species: {}", simulation.stats().species_count));
+                        if simulation.stats().stagnant {
+                            ui.label(RichText::new("Stagnant").color(Color32::YELLOW))
+                                .on_hover_text("Evolution seems to have stopped");
+                        }
+                    });
+
+                    ui.collapsing("Actions last tick", |ui| {
+                        let actions = simulation.stats().actions;
+                        egui::Grid::new("actions").show(ui, |ui| {
+                            for (name, count) in [
+                                ("Moved", actions.moved),
+                                ("Photosynthesized", actions.photosynthesized),
+                                ("Gave energy", actions.gave_energy),
+                                ("Attacked", actions.attacked),
+                                ("Recycled", actions.recycled),
+                                ("Reproduced", actions.reproduced),
+                            ] {
+                                ui.label(name);
+                                ui.label(count.to_string());
+                                ui.end_row();
+                            }
+                        });
+                    });
+
+                    ui.add_enabled_ui(simulation.dominant_genome().is_some(), |ui| {
+                        if ui
+                            .button("Copy dominant genome")
+                            .on_hover_text("Genome shared by the most alive bots")
+                            .clicked()
+                        {
+                            if let Some(genome) = simulation.dominant_genome() {
+                                let genome = gene::genome_to_string(genome);
+                                ctx.output_mut(|output| output.copied_text = genome);
+                            }
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Inject").clicked() {
+                            simulation_stopped |=
+                                channel_closed(simulation.inject_random(inject_count));
+                        }
+                        ui.add(DragValue::new(&mut inject_count).suffix(" random bots"));
+                    });
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Save state").clicked() {
+                            simulation_stopped |=
+                                channel_closed(simulation.save_state("state.json".into()));
+                        }
+                        if ui.button("Load state").clicked() {
+                            simulation_stopped |=
+                                channel_closed(simulation.load_state("state.json".into()));
+                        }
+                    });
+                    if let Some(message) = simulation.save_message() {
+                        ui.label(message);
+                    }
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Save config").clicked() {
+                            config_message = Some(
+                                match save::save_to_path("config.json", simulation.config()) {
+                                    Ok(()) => "Saved config.json".to_string(),
+                                    Err(error) => format!("Failed to save config.json: {error}"),
+                                },
+                            );
+                        }
+                        if ui.button("Load config").clicked() {
+                            let loaded = save::load_from_path::<Config>("config.json").and_then(
+                                |mut config| {
+                                    // Map can't be resized while running
+                                    let current = simulation.config();
+                                    config.width = current.width;
+                                    config.height = current.height;
+                                    config.cell_size = current.cell_size;
+
+                                    config.validate().map_err(Error::InvalidConfig)?;
+                                    Ok(config)
+                                },
+                            );
+
+                            config_message = Some(match loaded {
+                                Ok(config) => {
+                                    simulation_stopped |=
+                                        channel_closed(simulation.update_config(config));
+                                    "Loaded config.json".to_string()
+                                }
+                                Err(error) => format!("Failed to load config.json: {error}"),
+                            });
+                        }
+                    });
+                    #[cfg(feature = "watch-config")]
+                    if ui
+                        .checkbox(&mut watch_config, "Watch config.json")
+                        .on_hover_text("Reload the config whenever the file changes")
+                        .changed()
+                    {
+                        simulation_stopped |= channel_closed(
+                            simulation.watch_config(watch_config.then(|| "config.json".into())),
+                        );
+                    }
+                    if let Some(message) = &config_message {
+                        ui.label(message);
+                    }
+
+                    ui.checkbox(&mut paint_walls, "Paint walls")
+                        .on_hover_text("Left click places walls, right click erases cells");
+
+                    ui.collapsing("Plant genome", |ui| {
+                        ui.text_edit_singleline(&mut genome_string)
+                            .on_hover_text("Paste a genome copied with \"Copy dominant genome\"");
+
+                        match gene::genome_from_string(&genome_string) {
+                            Ok(genome) => {
+                                ui.horizontal(|ui| {
+                                    ui.checkbox(&mut plant_genome, "Plant on click");
+                                    if ui
+                                        .button("Minimize")
+                                        .on_hover_text("Remove genes that can never be executed")
+                                        .clicked()
+                                    {
+                                        genome_string =
+                                            gene::genome_to_string(&gene::minimize_genome(&genome));
+                                    }
+                                });
+                                ui.horizontal(|ui| {
+                                    if ui
+                                        .button("Release")
+                                        .on_hover_text("Place copies on random empty cells")
+                                        .clicked()
+                                    {
+                                        simulation_stopped |= channel_closed(
+                                            simulation.inject_genome(genome, invasion_count),
+                                        );
+                                    }
+                                    ui.add(DragValue::new(&mut invasion_count).suffix(" copies"));
+                                });
+                            }
+                            Err(error) => {
+                                plant_genome = false;
+                                if !genome_string.is_empty() {
+                                    ui.colored_label(Color32::RED, error);
+                                }
+                            }
+                        }
+                    });
                 });
 
             egui::Window::new("Settings")
@@ -62,75 +425,1119 @@ async fn main() {
 
                     ui.horizontal(|ui| {
                         ui.label("Mutation percent");
-                        ui.add(Slider::new(&mut config.mutation_percent, 0.0..=100.0));
+                        ui.add(Slider::new(
+                            &mut config.mutation_percent,
+                            MUTATION_PERCENT_RANGE,
+                        ));
+                    });
+                    ui.collapsing("Enabled instructions", |ui| {
+                        for instruction in Instruction::ALL {
+                            let mut enabled = config.enabled_instructions.contains(instruction);
+                            if ui
+                                .checkbox(&mut enabled, format!("{instruction:?}"))
+                                .changed()
+                            {
+                                config.enabled_instructions.set(instruction, enabled);
+                            }
+                        }
+                    })
+                    .header_response
+                    .on_hover_text(
+                        "Disabled instructions never appear in new genes, and act as Noop",
+                    );
+                    ui.horizontal(|ui| {
+                        ui.label("Reproduction");
+                        ui.radio_value(&mut config.crossover_mode, None, "Asexual");
+                        ui.radio_value(
+                            &mut config.crossover_mode,
+                            Some(CrossoverMode::SinglePoint),
+                            "Single-point crossover",
+                        );
+                        ui.radio_value(
+                            &mut config.crossover_mode,
+                            Some(CrossoverMode::Uniform),
+                            "Uniform crossover",
+                        );
+                    })
+                    .response
+                    .on_hover_text(
+                        "With crossover, cells mate with an alive neighbor, if they have one",
+                    );
+                    ui.horizontal(|ui| {
+                        ui.label("Newborns face");
+                        ui.radio_value(
+                            &mut config.newborn_faces,
+                            NewbornFacing::SameAsParent,
+                            "Same as parent",
+                        );
+                        ui.radio_value(
+                            &mut config.newborn_faces,
+                            NewbornFacing::AwayFromParent,
+                            "Opposite",
+                        );
+                        ui.radio_value(&mut config.newborn_faces, NewbornFacing::Random, "Random");
+                    });
+                    ui.checkbox(&mut config.always_mutate_color, "Always mutate color")
+                        .on_hover_text("Children get a slightly different color on every birth");
+                    ui.checkbox(&mut config.color_from_genome, "Color from genome")
+                        .on_hover_text(
+                            "In the Normal mode, bots with the same instructions look the same",
+                        );
+
+                    ui.horizontal(|ui| {
+                        let mut seasons_enabled = config.mutation_season_period.is_some();
+                        ui.checkbox(&mut seasons_enabled, "Mutation seasons");
+
+                        if seasons_enabled {
+                            let period = config.mutation_season_period.get_or_insert(1000);
+                            ui.add(
+                                DragValue::new(period)
+                                    .clamp_range(MUTATION_SEASON_PERIOD_RANGE)
+                                    .suffix(" ticks"),
+                            );
+                        } else {
+                            config.mutation_season_period = None;
+                        }
+                    });
+                    if config.mutation_season_period.is_some() {
+                        ui.horizontal(|ui| {
+                            ui.label("Peak mutation percent");
+                            ui.add(Slider::new(
+                                &mut config.mutation_peak_percent,
+                                MUTATION_PERCENT_RANGE,
+                            ));
+                        });
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.label("Branch mutations");
+                        let jitter_enabled = matches!(
+                            config.branch_mutation_mode,
+                            BranchMutationMode::LocalJitter(_)
+                        );
+                        if ui.radio(!jitter_enabled, "Uniform").clicked() {
+                            config.branch_mutation_mode = BranchMutationMode::Uniform;
+                        }
+                        if ui
+                            .radio(jitter_enabled, "Local jitter")
+                            .on_hover_text("Nudge branch targets instead of randomizing them")
+                            .clicked()
+                            && !jitter_enabled
+                        {
+                            config.branch_mutation_mode = BranchMutationMode::LocalJitter(1);
+                        }
+                        if let BranchMutationMode::LocalJitter(amount) =
+                            &mut config.branch_mutation_mode
+                        {
+                            ui.add(
+                                DragValue::new(amount)
+                                    .clamp_range(BRANCH_JITTER_RANGE)
+                                    .prefix("±"),
+                            );
+                        }
                     });
 
                     ui.horizontal(|ui| {
                         ui.label("Start energy");
                         ui.add(
                             DragValue::new(&mut config.start_energy)
-                                .clamp_range(0.0..=f32::INFINITY)
+                                .clamp_range(ENERGY_RANGE)
                                 .speed(0.1),
                         );
                     });
 
+                    ui.horizontal(|ui| {
+                        let mut range_enabled = config.start_energy_range.is_some();
+                        ui.checkbox(&mut range_enabled, "Random start energy");
+
+                        if range_enabled {
+                            let (min, max) = config
+                                .start_energy_range
+                                .get_or_insert((config.start_energy, config.start_energy * 2.));
+                            ui.add(DragValue::new(min).clamp_range(ENERGY_RANGE).speed(0.1));
+                            ui.label("to");
+                            ui.add(
+                                DragValue::new(max)
+                                    .clamp_range(*min..=*ENERGY_RANGE.end())
+                                    .speed(0.1),
+                            );
+                        } else {
+                            config.start_energy_range = None;
+                        }
+                    });
+
                     ui.horizontal(|ui| {
                         ui.label("Required energy for reproduction");
-                        ui.add(DragValue::new(&mut config.reproduction_required_energy));
+                        ui.add(
+                            DragValue::new(&mut config.reproduction_required_energy)
+                                .clamp_range(REPRODUCTION_ENERGY_RANGE),
+                        );
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Reproduction cooldown");
+                        ui.add(DragValue::new(&mut config.reproduction_cooldown).suffix(" ticks"));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Reproduction min age");
+                        ui.add(DragValue::new(&mut config.reproduction_min_age).suffix(" ticks"))
+                            .on_hover_text("Younger cells can't reproduce, regardless of energy");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Newborn protection");
+                        ui.add(
+                            DragValue::new(&mut config.newborn_invulnerable_ticks).suffix(" ticks"),
+                        )
+                        .on_hover_text("Attacks on children younger than this do nothing");
+                    });
+                    ui.checkbox(
+                        &mut config.reproduction_overtakes,
+                        "Children displace weaker cells",
+                    )
+                    .on_hover_text(
+                        "A child can be born over an alive cell with less energy than the parent",
+                    );
+                    ui.checkbox(&mut config.multicell, "Two-cell bodies")
+                        .on_hover_text("The Grow instruction adds a tail that follows the cell");
+
+                    ui.horizontal(|ui| {
+                        let mut split_enabled = config.child_energy_fraction.is_some();
+                        ui.checkbox(&mut split_enabled, "Give child a fraction of energy");
+
+                        if split_enabled {
+                            let fraction = config.child_energy_fraction.get_or_insert(0.5);
+                            ui.add(Slider::new(fraction, FRACTION_RANGE));
+                        } else {
+                            config.child_energy_fraction = None;
+                        }
                     });
 
                     ui.horizontal(|ui| {
                         ui.label("Max age");
-                        ui.add(DragValue::new(&mut config.cell_max_age));
+                        ui.add(DragValue::new(&mut config.cell_max_age).clamp_range(MAX_AGE_RANGE));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Max age jitter");
+                        ui.add(
+                            DragValue::new(&mut config.max_age_jitter)
+                                .clamp_range(MAX_AGE_JITTER_RANGE),
+                        );
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Instructions per tick");
+                        ui.add(
+                            DragValue::new(&mut config.instructions_per_tick)
+                                .clamp_range(INSTRUCTIONS_PER_TICK_RANGE),
+                        );
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Dead cells");
+                        ui.radio_value(
+                            &mut config.death_mode,
+                            DeathMode::LeaveCorpse,
+                            "Leave corpse",
+                        );
+                        ui.radio_value(&mut config.death_mode, DeathMode::Vanish, "Vanish");
+                    });
+                    ui.checkbox(
+                        &mut config.death_energy_to_neighbors,
+                        "Dying cells feed their neighbors",
+                    )
+                    .on_hover_text(
+                        "Energy of a dying cell is split between the alive cells around it",
+                    );
+                    ui.checkbox(&mut config.corpses_block, "Corpses block movement")
+                        .on_hover_text(
+                            "Otherwise cells can move or reproduce into corpses, destroying them",
+                        );
+
+                    ui.horizontal(|ui| {
+                        ui.label("Kill zone at the edges");
+                        ui.add(DragValue::new(&mut config.edge_kill_margin).suffix(" cells"))
+                            .on_hover_text("Only edges that don't wrap around");
+                    });
+
+                    ui.horizontal(|ui| {
+                        let mut overcrowding_enabled = config.overcrowd_death.is_some();
+                        ui.checkbox(&mut overcrowding_enabled, "Die with more neighbors than");
+
+                        if overcrowding_enabled {
+                            let max_neighbors = config.overcrowd_death.get_or_insert(6);
+                            ui.add(DragValue::new(max_neighbors).clamp_range(OVERCROWD_RANGE));
+                        } else {
+                            config.overcrowd_death = None;
+                        }
                     });
 
                     ui.horizontal(|ui| {
                         ui.label("Photosynthesis energy");
-                        ui.add(DragValue::new(&mut config.photosynthesis_energy).speed(0.01));
+                        ui.add(
+                            DragValue::new(&mut config.photosynthesis_energy)
+                                .clamp_range(ENERGY_RANGE)
+                                .speed(0.01),
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        let mut resources_enabled = config.resource_cap.is_some();
+                        ui.checkbox(&mut resources_enabled, "Limited resources")
+                            .on_hover_text("Photosynthesis draws from a resource that regrows");
+
+                        if resources_enabled {
+                            let cap = config.resource_cap.get_or_insert(5.0);
+                            ui.add(
+                                DragValue::new(cap)
+                                    .clamp_range(ENERGY_RANGE)
+                                    .prefix("cap: "),
+                            );
+                            ui.add(
+                                DragValue::new(&mut config.resource_regrowth)
+                                    .clamp_range(ENERGY_RANGE)
+                                    .speed(0.01)
+                                    .prefix("regrowth: "),
+                            );
+                        } else {
+                            config.resource_cap = None;
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Ambient energy per tick");
+                        ui.add(
+                            DragValue::new(&mut config.ambient_energy_per_tick)
+                                .clamp_range(ENERGY_RANGE)
+                                .speed(0.01),
+                        );
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Light comes from");
+                        ui.radio_value(&mut config.light_axis, LightAxis::Y, "Bottom");
+                        ui.radio_value(&mut config.light_axis, LightAxis::X, "Right");
+                        ui.radio_value(&mut config.light_axis, LightAxis::Radial, "Center");
+                    });
+                    ui.checkbox(
+                        &mut config.directional_photosynthesis,
+                        "Directional photosynthesis",
+                    )
+                    .on_hover_text("Bots get full energy only when facing the light");
+                    ui.checkbox(&mut config.shading, "Shading")
+                        .on_hover_text("Alive cells halve photosynthesis of the cell behind them");
+
+                    ui.horizontal(|ui| {
+                        let mut cycle_enabled = config.day_night_period.is_some();
+                        ui.checkbox(&mut cycle_enabled, "Day/night cycle");
+
+                        if cycle_enabled {
+                            let period = config.day_night_period.get_or_insert(1000);
+                            ui.add(
+                                DragValue::new(period)
+                                    .clamp_range(DAY_NIGHT_PERIOD_RANGE)
+                                    .suffix(" ticks"),
+                            );
+                        } else {
+                            config.day_night_period = None;
+                        }
                     });
+                    if config.day_night_period.is_some() {
+                        ui.horizontal(|ui| {
+                            ui.label("Night light");
+                            ui.add(Slider::new(&mut config.night_light, LIGHT_RANGE));
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Day light");
+                            ui.add(Slider::new(&mut config.day_light, LIGHT_RANGE));
+                        });
+                    }
                     ui.horizontal(|ui| {
                         ui.label("Attack energy");
-                        ui.add(DragValue::new(&mut config.attack_energy).speed(0.05));
+                        ui.add(
+                            DragValue::new(&mut config.attack_energy)
+                                .clamp_range(ENERGY_RANGE)
+                                .speed(0.05),
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Attack range");
+                        ui.add(
+                            DragValue::new(&mut config.attack_range)
+                                .clamp_range(ATTACK_RANGE_RANGE)
+                                .suffix(" cells"),
+                        )
+                        .on_hover_text("0 attacks only the cell in front");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Vision range");
+                        ui.add(
+                            DragValue::new(&mut config.vision_range)
+                                .clamp_range(VISION_RANGE_RANGE)
+                                .suffix(" cells"),
+                        )
+                        .on_hover_text("How far CheckAtDistance can look");
+                    });
+                    ui.checkbox(&mut config.attack_kin, "Attack relatives");
+                    ui.horizontal(|ui| {
+                        ui.label("Attacking relatives costs");
+                        ui.add(
+                            DragValue::new(&mut config.kin_attack_penalty)
+                                .clamp_range(ENERGY_RANGE)
+                                .speed(0.1),
+                        )
+                        .on_hover_text("When above 0, attacks on relatives only hurt the attacker");
+                    });
+                    ui.checkbox(&mut config.attack_steals_gene, "Attacks steal a gene")
+                        .on_hover_text("Attacker copies a random gene from its victim");
+                    ui.horizontal(|ui| {
+                        ui.label("Relatives share");
+                        ui.radio_value(
+                            &mut config.kin_compare_mode,
+                            KinCompareMode::InstructionsOnly,
+                            "Instructions",
+                        );
+                        ui.radio_value(
+                            &mut config.kin_compare_mode,
+                            KinCompareMode::FullGene,
+                            "Whole genes",
+                        );
                     });
 
                     ui.horizontal(|ui| {
                         ui.label("Movement cost");
-                        ui.add(DragValue::new(&mut config.movement_cost).speed(0.01));
+                        ui.add(
+                            DragValue::new(&mut config.movement_cost)
+                                .clamp_range(ENERGY_RANGE)
+                                .speed(0.01),
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Phototaxis cost");
+                        ui.add(
+                            DragValue::new(&mut config.phototaxis_cost)
+                                .clamp_range(ENERGY_RANGE)
+                                .speed(0.01),
+                        )
+                        .on_hover_text(
+                            "Extra cost of moving towards the light, for the whole gradient",
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Turn cost");
+                        ui.add(
+                            DragValue::new(&mut config.turn_cost)
+                                .clamp_range(ENERGY_RANGE)
+                                .speed(0.01),
+                        );
                     });
                     ui.horizontal(|ui| {
                         ui.label("Noop cost");
-                        ui.add(DragValue::new(&mut config.noop_cost));
+                        ui.add(DragValue::new(&mut config.noop_cost).clamp_range(ENERGY_RANGE));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Sense cost");
+                        ui.add(
+                            DragValue::new(&mut config.sense_cost)
+                                .clamp_range(ENERGY_RANGE)
+                                .speed(0.01),
+                        )
+                        .on_hover_text("Paid by every Check* instruction");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Sensing noise");
+                        ui.add(
+                            DragValue::new(&mut config.sensing_noise)
+                                .clamp_range(ENERGY_RANGE)
+                                .speed(0.01),
+                        )
+                        .on_hover_text("Largest error of energy readings");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Upkeep");
+                        ui.add(
+                            DragValue::new(&mut config.min_energy_upkeep)
+                                .clamp_range(ENERGY_RANGE)
+                                .speed(0.01),
+                        )
+                        .on_hover_text("Paid by every alive cell each tick, whatever it does");
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut config.wrap_x, "Wrap horizontally");
+                        ui.checkbox(&mut config.wrap_y, "Wrap vertically");
+                    });
+
+                    ui.checkbox(&mut config.sparse_map, "Sparse map")
+                        .on_hover_text("Takes effect after the map is reset");
+
+                    ui.horizontal(|ui| {
+                        ui.label("Seeding pattern");
+                        egui::ComboBox::from_id_source("seeding_pattern")
+                            .selected_text(format!("{:?}", config.seeding_pattern))
+                            .show_ui(ui, |ui| {
+                                for pattern in [
+                                    SeedingPattern::UniformRandom,
+                                    SeedingPattern::CenterClump,
+                                    SeedingPattern::Corners,
+                                    SeedingPattern::Checkerboard,
+                                    SeedingPattern::SingleBot,
+                                ] {
+                                    ui.selectable_value(
+                                        &mut config.seeding_pattern,
+                                        pattern,
+                                        format!("{:?}", pattern),
+                                    );
+                                }
+                            });
+                    })
+                    .response
+                    .on_hover_text("Takes effect after the map is reset");
+
+                    ui.horizontal(|ui| {
+                        ui.label("Initial direction");
+                        egui::ComboBox::from_id_source("initial_direction")
+                            .selected_text(match config.initial_direction {
+                                Some(direction) => format!("{direction:?}"),
+                                None => "Random".to_string(),
+                            })
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut config.initial_direction, None, "Random");
+                                for direction in [
+                                    Direction::Left,
+                                    Direction::Right,
+                                    Direction::Up,
+                                    Direction::Down,
+                                ] {
+                                    ui.selectable_value(
+                                        &mut config.initial_direction,
+                                        Some(direction),
+                                        format!("{direction:?}"),
+                                    );
+                                }
+                            });
+                    })
+                    .response
+                    .on_hover_text("Direction of spawned bots. Children follow \"Newborns face\"");
+
+                    ui.horizontal(|ui| {
+                        let mut pool_enabled = config.initial_genome_pool.is_some();
+                        ui.checkbox(&mut pool_enabled, "Start with a pool of");
+
+                        if pool_enabled {
+                            let pool = config.initial_genome_pool.get_or_insert(10);
+                            ui.add(
+                                DragValue::new(pool)
+                                    .clamp_range(INITIAL_GENOME_POOL_RANGE)
+                                    .suffix(" genomes"),
+                            );
+                        } else {
+                            config.initial_genome_pool = None;
+                        }
+                    })
+                    .response
+                    .on_hover_text("Takes effect after the map is reset");
+
+                    ui.horizontal(|ui| {
+                        ui.label("Species are");
+                        let similar = matches!(
+                            config.species_definition,
+                            SpeciesDefinition::SimilarityRadius(_)
+                        );
+
+                        if ui.radio(!similar, "Identical").clicked() {
+                            config.species_definition = SpeciesDefinition::IdenticalInstructions;
+                        }
+                        if ui.radio(similar, "Similar").clicked() && !similar {
+                            config.species_definition = SpeciesDefinition::SimilarityRadius(2);
+                        }
+                        if let SpeciesDefinition::SimilarityRadius(radius) =
+                            &mut config.species_definition
+                        {
+                            ui.add(
+                                DragValue::new(radius)
+                                    .clamp_range(SPECIES_RADIUS_RANGE)
+                                    .suffix(" genes"),
+                            );
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        let mut detect = config.stagnation_window.is_some();
+                        ui.checkbox(&mut detect, "Stagnant after");
+
+                        if detect {
+                            let window = config.stagnation_window.get_or_insert(10000);
+                            ui.add(
+                                DragValue::new(window)
+                                    .clamp_range(STAGNATION_WINDOW_RANGE)
+                                    .suffix(" ticks"),
+                            );
+                            ui.label("within");
+                            ui.add(
+                                DragValue::new(&mut config.stagnation_threshold)
+                                    .clamp_range(FRACTION_RANGE)
+                                    .speed(0.001),
+                            );
+                        } else {
+                            config.stagnation_window = None;
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Flags the run once the dominant species and instruction shares \
+                         stop changing",
+                    );
+
+                    #[cfg(feature = "image")]
+                    ui.horizontal(|ui| {
+                        let mut timelapse_enabled = config.timelapse_interval.is_some();
+                        ui.checkbox(&mut timelapse_enabled, "Time-lapse every");
+
+                        if timelapse_enabled {
+                            let interval = config.timelapse_interval.get_or_insert(100);
+                            ui.add(
+                                DragValue::new(interval)
+                                    .clamp_range(TIMELAPSE_INTERVAL_RANGE)
+                                    .suffix(" ticks"),
+                            );
+                            ui.label("to");
+                            if ui.text_edit_singleline(&mut timelapse_dir).lost_focus() {
+                                simulation_stopped |= channel_closed(
+                                    simulation.set_timelapse_dir(timelapse_dir.clone().into()),
+                                );
+                            }
+                        } else {
+                            config.timelapse_interval = None;
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        let mut throttle_enabled = config.max_ticks_per_frame.is_some();
+                        ui.checkbox(&mut throttle_enabled, "At most").on_hover_text(
+                            "Keeps the simulation from running ahead of the rendering",
+                        );
+
+                        if throttle_enabled {
+                            let ticks = config.max_ticks_per_frame.get_or_insert(10);
+                            ui.add(DragValue::new(ticks).clamp_range(MAX_TICKS_PER_FRAME_RANGE));
+                        } else {
+                            config.max_ticks_per_frame = None;
+                        }
+                        ui.label("ticks per frame");
+                    });
+
+                    ui.horizontal(|ui| {
+                        let mut ascii_enabled = config.ascii_interval.is_some();
+                        ui.checkbox(&mut ascii_enabled, "Print ASCII map every")
+                            .on_hover_text("Prints the map to the terminal");
+
+                        if ascii_enabled {
+                            let interval = config.ascii_interval.get_or_insert(100);
+                            ui.add(
+                                DragValue::new(interval)
+                                    .clamp_range(ASCII_INTERVAL_RANGE)
+                                    .suffix(" ticks"),
+                            );
+                        } else {
+                            config.ascii_interval = None;
+                        }
                     });
 
                     if config != *simulation.config() {
-                        simulation
-                            .update_config(config)
-                            .expect("Failed to update the simulation configuration");
+                        match config.validate() {
+                            Ok(()) => {
+                                simulation_stopped |=
+                                    channel_closed(simulation.update_config(config));
+                            }
+                            Err(error) => {
+                                ui.colored_label(Color32::RED, error);
+                            }
+                        }
                     }
                 });
 
+            // Simulation can't be borrowed mutably while the selected cell is, so the change is sent after the window
+            let mut set_frozen = None;
+            // Selection is cleared on reset, which hides this window
+            if let Some(bot) = simulation.selected_bot() {
+                egui::Window::new("Selected cell")
+                    .resizable(false)
+                    .show(ctx, |ui| {
+                        ui.label(format!("Position: {}, {}", bot.x, bot.y));
+                        ui.label(if bot.alive {
+                            "Alive"
+                        } else if bot.wall {
+                            "Wall"
+                        } else if bot.is_dead() {
+                            "Dead"
+                        } else {
+                            "Empty"
+                        });
+                        ui.label(format!("Energy: {:.2}", bot.energy));
+                        ui.label(format!(
+                            "Photosynthesis at this cell: {:.2}",
+                            simulation.config().photosynthesis_yield(
+                                bot.x,
+                                bot.y,
+                                simulation.iterations()
+                            )
+                        ))
+                        .on_hover_text("Less when not facing the light, or when shaded");
+                        if let Some(index) = bot.trace().last() {
+                            ui.label(format!(
+                                "Last tick: {:+.2} energy, {:?}",
+                                bot.energy - bot.energy_last_tick,
+                                bot.genome[index as usize].instruction
+                            ));
+                        }
+                        ui.label(format!("Age: {}", bot.age));
+                        ui.label(format!("Direction: {:?}", bot.direction));
+                        if ui
+                            .add_enabled(
+                                bot.alive,
+                                egui::Checkbox::new(&mut follow_selected, "Follow this cell"),
+                            )
+                            .on_disabled_hover_text("Only alive cells can be followed")
+                            .changed()
+                            && !follow_selected
+                        {
+                            camera.stop_following();
+                        }
+                        let mut frozen = bot.frozen;
+                        if ui
+                            .add_enabled(bot.alive, egui::Checkbox::new(&mut frozen, "Freeze"))
+                            .on_hover_text("Stop this cell, while the rest of the world runs")
+                            .changed()
+                        {
+                            set_frozen = Some(frozen);
+                        }
+                        ui.add_enabled(
+                            bot.alive || bot.is_dead(),
+                            egui::Checkbox::new(&mut stamp_selected, "Stamp genome on click"),
+                        )
+                        .on_hover_text("Left click places copies of this cell, with fresh energy");
+                        ui.label(format!(
+                            "Lineage: {:016x} ({} alive)",
+                            bot.lineage,
+                            simulation.find_by_lineage(bot.lineage).len()
+                        ));
+                        ui.label(match simulation.selected_lineage_established() {
+                            Some(iteration) => format!("Lineage established at {iteration}"),
+                            None => "Lineage hasn't reproduced yet".to_string(),
+                        })
+                        .on_hover_text("Iteration on which the lineage first reproduced");
+                        ui.checkbox(&mut highlight_lineage, "Highlight lineage");
+
+                        ui.collapsing("Trace", |ui| {
+                            // Oldest instruction first
+                            for index in bot.trace().iter() {
+                                let instruction = bot.genome[index as usize].instruction;
+                                ui.label(format!("{index}: {instruction:?}"));
+                            }
+                        });
+
+                        ui.collapsing("Genome", |ui| {
+                            // Genes that can never be executed are grayed out
+                            let reachable = gene::reachable_instructions(&bot.genome);
+
+                            egui::Grid::new("genome").striped(true).show(ui, |ui| {
+                                for header in ["#", "Instruction", "Option", "Energy", "B1", "B2"] {
+                                    ui.strong(header);
+                                }
+                                ui.end_row();
+
+                                for (i, gene) in bot.genome.iter().enumerate() {
+                                    let cell = |text: String| {
+                                        if reachable[i] {
+                                            RichText::new(text)
+                                        } else {
+                                            RichText::new(text).color(Color32::DARK_GRAY)
+                                        }
+                                    };
+
+                                    ui.label(cell(i.to_string()));
+                                    ui.label(cell(format!("{:?}", gene.instruction)));
+                                    ui.label(cell(gene.option.to_string()));
+                                    ui.label(cell(format!("{:.2}", gene.energy)));
+                                    ui.label(cell(gene.branch.to_string()));
+                                    ui.label(cell(gene.branch_alt.to_string()));
+                                    ui.end_row();
+                                }
+                            });
+                        });
+                    });
+            }
+
+            if let Some(frozen) = set_frozen {
+                simulation_stopped |= channel_closed(simulation.set_selected_frozen(frozen));
+            }
+
+            // Preview of the stamped genome, next to the cursor
+            let stamp_source = simulation
+                .selected_bot()
+                .filter(|bot| stamp_selected && (bot.alive || bot.is_dead()));
+            if let (Some(source), false) = (stamp_source, pointer_over_ui) {
+                egui::show_tooltip_at_pointer(ctx, egui::Id::new("stamp_preview"), |ui| {
+                    ui.label(format!("Stamping cell {}, {}", source.x, source.y));
+                    let reachable = gene::reachable_instructions(&source.genome);
+                    let instructions: Vec<String> = source
+                        .genome
+                        .iter()
+                        .zip(reachable)
+                        .filter(|&(_, reachable)| reachable)
+                        .map(|(gene, _)| format!("{:?}", gene.instruction))
+                        .collect();
+                    ui.label(instructions.join(", "));
+                });
+            }
+
+            // Shift-click selects the second bot
+            if let (Some(first), Some(second)) =
+                (simulation.selected_bot(), simulation.secondary_bot())
+            {
+                egui::Window::new("Genome diff")
+                    .resizable(false)
+                    .show(ctx, |ui| {
+                        ui.label(format!(
+                            "{}, {} against {}, {}",
+                            first.x, first.y, second.x, second.y
+                        ));
+
+                        let differing = (0..first.genome.len())
+                            .filter(|&i| first.genome[i] != second.genome[i])
+                            .collect::<Vec<_>>();
+                        if differing.is_empty() {
+                            ui.label("Genomes are identical");
+                            return;
+                        }
+                        ui.label(format!("{} genes differ", differing.len()));
+
+                        egui::Grid::new("genome diff").striped(true).show(ui, |ui| {
+                            for header in ["#", "", "Instruction", "Option", "Energy", "B1", "B2"] {
+                                ui.strong(header);
+                            }
+                            ui.end_row();
+
+                            for i in differing {
+                                for (side, bot) in [("1", first), ("2", second)] {
+                                    let gene = &bot.genome[i];
+                                    ui.label(if side == "1" {
+                                        i.to_string()
+                                    } else {
+                                        String::new()
+                                    });
+                                    ui.label(side);
+                                    ui.label(format!("{:?}", gene.instruction));
+                                    ui.label(gene.option.to_string());
+                                    ui.label(format!("{:.2}", gene.energy));
+                                    ui.label(gene.branch.to_string());
+                                    ui.label(gene.branch_alt.to_string());
+                                    ui.end_row();
+                                }
+                            }
+                        });
+                    });
+            }
+
+            egui::Window::new("Instruction distribution")
+                .default_open(false)
+                .show(ctx, |ui| {
+                    let bars = simulation
+                        .instruction_histogram()
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &count)| {
+                            Bar::new(i as f64, count as f64)
+                                .name(format!("{:?}", Instruction::ALL[i]))
+                        })
+                        .collect();
+
+                    Plot::new("instruction_histogram")
+                        .height(200.0)
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .show(ui, |plot_ui| plot_ui.bar_chart(BarChart::new(bars)));
+                });
+
+            egui::Window::new("Energy distribution")
+                .default_open(false)
+                .show(ctx, |ui| {
+                    let mut config = *simulation.config();
+                    ui.horizontal(|ui| {
+                        ui.label("Buckets");
+                        ui.add(
+                            DragValue::new(&mut config.energy_histogram_buckets)
+                                .clamp_range(HISTOGRAM_BUCKETS_RANGE),
+                        );
+                        ui.label("Max energy");
+                        ui.add(
+                            DragValue::new(&mut config.energy_histogram_max)
+                                .clamp_range(HISTOGRAM_MAX_ENERGY_RANGE),
+                        );
+                    });
+                    if config != *simulation.config() {
+                        simulation_stopped |= channel_closed(simulation.update_config(config));
+                    }
+
+                    let bucket_width =
+                        config.energy_histogram_max as f64 / config.energy_histogram_buckets as f64;
+                    let bars = simulation
+                        .energy_histogram()
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &count)| {
+                            Bar::new((i as f64 + 0.5) * bucket_width, count as f64)
+                                .width(bucket_width)
+                        })
+                        .collect();
+
+                    Plot::new("energy_histogram")
+                        .height(200.0)
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .show(ui, |plot_ui| plot_ui.bar_chart(BarChart::new(bars)));
+                });
+
+            egui::Window::new("Genome complexity")
+                .default_open(false)
+                .show(ctx, |ui| {
+                    ui.label(format!(
+                        "Average executable genes: {:.2}",
+                        simulation.stats().average_complexity
+                    ))
+                    .on_hover_text("Reachable genes other than Noop, averaged over alive cells");
+                    Plot::new("complexity_history")
+                        .height(200.0)
+                        .include_y(0.0)
+                        .show(ui, |plot_ui| {
+                            plot_ui.line(Line::new(PlotPoints::new(complexity_history.clone())))
+                        });
+                });
+
             egui::Window::new("Rendering mode")
                 .resizable(false)
                 .show(ctx, |ui| {
                     ui.radio_value(&mut rendering_mode, RenderingMode::Normal, "Normal");
-                    ui.radio_value(&mut rendering_mode, RenderingMode::Energy, "Energy");
+                    ui.horizontal(|ui| {
+                        let log_scale = match rendering_mode {
+                            RenderingMode::Energy(log_scale) => Some(log_scale),
+                            _ => None,
+                        };
+                        if ui.radio(log_scale.is_some(), "Energy").clicked() && log_scale.is_none()
+                        {
+                            rendering_mode = RenderingMode::Energy(false);
+                        }
+                        if let RenderingMode::Energy(log_scale) = &mut rendering_mode {
+                            ui.checkbox(log_scale, "logarithmic")
+                                .on_hover_text("Shows the differences when energies vary a lot");
+                        }
+                    });
                     ui.radio_value(&mut rendering_mode, RenderingMode::Lifetime, "Lifetime");
+                    ui.horizontal(|ui| {
+                        let blend = match rendering_mode {
+                            RenderingMode::InstructionPointer(blend) => Some(blend),
+                            _ => None,
+                        };
+                        if ui
+                            .radio(blend.is_some(), "Instruction pointer")
+                            .on_hover_text("Cells further in their genome are brighter")
+                            .clicked()
+                            && blend.is_none()
+                        {
+                            rendering_mode = RenderingMode::InstructionPointer(false);
+                        }
+                        if let RenderingMode::InstructionPointer(blend) = &mut rendering_mode {
+                            ui.checkbox(blend, "over cell colors");
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut rendering_mode, RenderingMode::Density, "Density");
+                        ui.add(
+                            DragValue::new(&mut density_window)
+                                .clamp_range(DENSITY_WINDOW_RANGE)
+                                .prefix("window: "),
+                        );
+                    });
+                    ui.checkbox(&mut light_background, "Show light on empty cells");
+                    ui.checkbox(&mut ghost_border, "Show wrapped edges")
+                        .on_hover_text("Repeat the opposite edge around the map, where it wraps");
+                    if ui
+                        .button("Load palette.json")
+                        .on_hover_text("Colors missing from the file keep their defaults")
+                        .clicked()
+                    {
+                        palette_message = Some(match Palette::load("palette.json") {
+                            Ok(loaded) => {
+                                palette = loaded;
+                                redraw_grid = true;
+                                "Loaded palette.json".to_string()
+                            }
+                            Err(error) => format!("Failed to load palette.json: {error}"),
+                        });
+                    }
+                    if let Some(message) = &palette_message {
+                        ui.label(message);
+                    }
+
+                    ui.separator();
+                    if ui
+                        .checkbox(&mut follow_energetic, "Follow the most energetic cell")
+                        .changed()
+                        && !follow_energetic
+                    {
+                        camera.stop_following();
+                    }
+
+                    if ui
+                        .checkbox(&mut adaptive_tps, "Adaptive TPS")
+                        .on_hover_text("Slow the simulation down as ticks get longer")
+                        .changed()
+                    {
+                        simulation_stopped |=
+                            channel_closed(simulation.set_adaptive_tps(adaptive_tps));
+                    }
+                    ui.horizontal(|ui| {
+                        let mut cap_enabled = fps_cap.is_some();
+                        ui.checkbox(&mut cap_enabled, "FPS cap");
+
+                        if cap_enabled {
+                            let cap = fps_cap.get_or_insert(60);
+                            ui.add(DragValue::new(cap).clamp_range(1..=240));
+                        } else {
+                            fps_cap = None;
+                        }
+                    });
                 });
         });
 
-        let config = simulation.config();
-        for x in 0..config.width {
-            for y in 0..config.height {
-                let cell = simulation.map().get(x, y).unwrap();
+        if follow_selected {
+            let cell_size = simulation.config().cell_size as f32;
+            match simulation.selected_bot() {
+                Some(bot) if bot.alive => camera.follow(
+                    (bot.x as f32 + 0.5) * cell_size,
+                    (bot.y as f32 + 0.5) * cell_size,
+                ),
+                // The cell has died, or the selection was cleared
+                _ => {
+                    follow_selected = false;
+                    camera.stop_following();
+                }
+            }
+        } else if follow_energetic && get_time() - last_recenter > 0.5 {
+            if let Some((x, y)) = simulation.max_energy_cell() {
+                let cell_size = simulation.config().cell_size as f32;
+                camera.follow((x as f32 + 0.5) * cell_size, (y as f32 + 0.5) * cell_size);
+            }
+            last_recenter = get_time();
+        }
+        camera.update(!pointer_over_ui);
+
+        if !pointer_over_ui {
+            let config = *simulation.config();
+            let (mouse_x, mouse_y) = mouse_position();
+            let (grid_x, grid_y) = camera.screen_to_grid(mouse_x, mouse_y);
+            // Negative coordinates are saturated to 0 by the cast, so they have to be skipped
+            let on_grid = grid_x >= 0. && grid_y >= 0.;
+            let x = grid_x as usize / config.cell_size;
+            let y = grid_y as usize / config.cell_size;
+
+            let cell = if on_grid {
+                simulation.map().get(x, y)
+            } else {
+                None
+            };
+
+            if let Some(cell) = cell {
+                if paint_walls {
+                    if is_mouse_button_down(MouseButton::Left) && !cell.wall {
+                        simulation_stopped |=
+                            channel_closed(simulation.set_cell(x, y, Bot::new_wall(x, y)));
+                    } else if is_mouse_button_down(MouseButton::Right) && !cell.empty {
+                        simulation_stopped |=
+                            channel_closed(simulation.set_cell(x, y, Bot::new_empty(x, y)));
+                    }
+                } else if is_mouse_button_pressed(MouseButton::Left) {
+                    match gene::genome_from_string(&genome_string) {
+                        Ok(genome) if plant_genome => {
+                            let bot = Bot::new_with_genome(x, y, genome, &config);
+                            simulation_stopped |= channel_closed(simulation.set_cell(x, y, bot));
+                        }
+                        // Copies keep the lineage and color, so they can be told apart
+                        _ if stamp_selected
+                            && simulation
+                                .selected_bot()
+                                .is_some_and(|bot| bot.alive || bot.is_dead()) =>
+                        {
+                            let source = *simulation.selected_bot().unwrap();
+                            let mut bot = Bot::new_with_genome(x, y, source.genome, &config);
+                            bot.lineage = source.lineage;
+                            bot.color = source.color;
+                            simulation_stopped |= channel_closed(simulation.set_cell(x, y, bot));
+                        }
+                        _ if is_key_down(KeyCode::LeftShift)
+                            || is_key_down(KeyCode::RightShift) =>
+                        {
+                            simulation_stopped |=
+                                channel_closed(simulation.select_secondary_bot(x, y));
+                        }
+                        _ => {
+                            simulation_stopped |= channel_closed(simulation.select_bot(x, y));
+                        }
+                    }
+                }
+            }
+        }
+
+        redraw_grid |= rendering_mode != drawn_rendering_mode;
+        redraw_grid |= light_background != drawn_light_background;
+        redraw_grid |= density_window != drawn_density_window;
+        redraw_grid |= highlight_lineage != drawn_highlight_lineage;
+        if redraw_grid {
+            set_camera(&Camera2D {
+                render_target: Some(grid),
+                ..Camera2D::from_display_rect(Rect::new(0., 0., grid_width, grid_height))
+            });
+            clear_background(palette.background.into());
 
-                if cell.empty {
+            let config = simulation.config();
+            if light_background {
+                // Cells are drawn on top of this, so only the empty ones show it
+                for y in 0..config.height {
+                    for x in 0..config.width {
+                        let light = config.light_multiplier(x, y, simulation.iterations());
+                        draw_rectangle(
+                            (x * config.cell_size) as f32,
+                            (y * config.cell_size) as f32,
+                            config.cell_size as f32,
+                            config.cell_size as f32,
+                            (palette.light * light as f64).into(),
+                        );
+                    }
+                }
+            }
+            if rendering_mode == RenderingMode::Density {
+                let field = renderer::density_field(simulation.map(), density_window);
+                for (i, &density) in field.iter().enumerate() {
+                    draw_rectangle(
+                        (i % config.width * config.cell_size) as f32,
+                        (i / config.width * config.cell_size) as f32,
+                        config.cell_size as f32,
+                        config.cell_size as f32,
+                        renderer::density_color(density).into(),
+                    );
+                }
+            }
+            for (x, y, cell) in simulation.map().iter() {
+                // In the Density mode, only walls are drawn on top of the density field
+                if cell.empty || (rendering_mode == RenderingMode::Density && !cell.wall) {
                     continue;
                 }
 
-                let color = if cell.alive {
-                    rendering_mode.render(cell, config).into()
+                let color = if cell.wall {
+                    palette.wall.into()
+                } else if cell.alive {
+                    rendering_mode.render(cell, config, &palette).into()
                 } else {
-                    Color::from_rgba(100, 100, 100, 255)
+                    palette.dead.into()
                 };
 
                 draw_rectangle(
@@ -141,9 +1548,61 @@ async fn main() {
                     color,
                 );
             }
+
+            // Energy and Lifetime modes never use magenta,
+            // and the outline keeps it distinct from random colors in Normal mode
+            if let Some(selected) = simulation.selected_bot().filter(|_| highlight_lineage) {
+                for (x, y, _) in simulation
+                    .map()
+                    .iter()
+                    .filter(|(_, _, cell)| cell.alive && cell.lineage == selected.lineage)
+                {
+                    draw_rectangle(
+                        (x * config.cell_size) as f32,
+                        (y * config.cell_size) as f32,
+                        config.cell_size as f32,
+                        config.cell_size as f32,
+                        Color::new(1., 0., 1., 0.6),
+                    );
+                    draw_rectangle_lines(
+                        (x * config.cell_size) as f32,
+                        (y * config.cell_size) as f32,
+                        config.cell_size as f32,
+                        config.cell_size as f32,
+                        1.,
+                        WHITE,
+                    );
+                }
+            }
+
+            set_default_camera();
+            redraw_grid = false;
+            drawn_rendering_mode = rendering_mode;
+            drawn_light_background = light_background;
+            drawn_density_window = density_window;
+            drawn_highlight_lineage = highlight_lineage;
+        }
+        camera.draw_grid(grid.texture, grid_width, grid_height);
+        if ghost_border {
+            let config = simulation.config();
+            camera.draw_ghost_border(
+                grid.texture,
+                vec2(grid_width, grid_height),
+                config.cell_size as f32,
+                config.wrap_x,
+                config.wrap_y,
+            );
         }
+
         egui_macroquad::draw();
 
+        if let Some(fps_cap) = fps_cap {
+            let remaining = 1.0 / fps_cap as f64 - (get_time() - frame_start);
+            if remaining > 0.0 {
+                std::thread::sleep(std::time::Duration::from_secs_f64(remaining));
+            }
+        }
+
         next_frame().await;
     }
 }
@@ -1,38 +1,200 @@
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
 use crate::{
-    simulation::{bot::Bot, color::Color},
-    Config,
+    error::Result,
+    simulation::{bot::Bot, color::Color, map::Map},
+    Config, GENOME_LENGTH,
 };
 
-#[derive(PartialEq)]
+pub const DENSITY_WINDOW_RANGE: std::ops::RangeInclusive<usize> = 1..=31;
+/// Energy shown as the brightest green in the logarithmic Energy mode
+const LOG_ENERGY_MAX: f64 = 10_000.;
+
+/// Colors used to draw the grid, other than the cells' own colors
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Palette {
+    /// Energy mode fades to this color up to 5 times the reproduction energy
+    pub low_energy: Color,
+    /// Energy mode fades to this color for cells with even more energy
+    pub high_energy: Color,
+    /// Lifetime mode color of newborn cells
+    pub young: Color,
+    /// Lifetime mode color of cells at the max age
+    pub old: Color,
+    pub dead: Color,
+    pub wall: Color,
+    pub background: Color,
+    /// Drawn on empty cells, dimmed by the light level of their row
+    pub light: Color,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            low_energy: Color::new(255, 255, 0),
+            high_energy: Color::new(0, 255, 0),
+            young: Color::new(10, 0, 0),
+            old: Color::new(255, 0, 0),
+            dead: Color::new(100, 100, 100),
+            wall: Color::new(170, 170, 210),
+            background: Color::BLACK,
+            light: Color::new(0, 31, 51),
+        }
+    }
+}
+
+impl Palette {
+    /// Read a palette from a JSON file. Colors missing from the file keep their defaults
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let json = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
 pub enum RenderingMode {
-    /// Show original cell colors,
+    /// Show original cell colors, or colors derived from the genome,
+    /// see [`Config::color_from_genome`]
     Normal,
-    /// More energy the cell has, brightner yellow color
-    Energy,
+    /// More energy the cell has, brightner yellow color.
+    /// If the field is true, energy is scaled logarithmically, so that a few cells
+    /// hoarding energy don't saturate the rest
+    Energy(bool),
     /// Older cells have darker color
     Lifetime,
+    /// Every cell, including empty ones, is colored by the amount of alive cells around it.
+    /// Colors come from [`density_field`] and [`density_color`], instead of [`RenderingMode::render`]
+    Density,
+    /// Cells further in their genome are brighter, so clones running in lockstep pulse together.
+    /// If the field is true, original cell colors are dimmed instead of gray
+    InstructionPointer(bool),
 }
 
 impl RenderingMode {
-    pub fn render(&self, bot: &Bot, config: &Config) -> Color {
+    pub fn render(&self, bot: &Bot, config: &Config, palette: &Palette) -> Color {
         let reproduction_required_energy = config.reproduction_required_energy as f32;
 
         match self {
+            Self::Normal if config.color_from_genome => {
+                Color::from_hash(bot.instruction_fingerprint())
+            }
             Self::Normal => bot.color,
-            Self::Energy => {
+            Self::Energy(true) => {
+                let t = (bot.energy.max(0.) as f64).ln_1p() / LOG_ENERGY_MAX.ln_1p();
+                // Same colors as the linear scale: black to yellow, then yellow to green
+                if t < 0.5 {
+                    Color::BLACK.lerp(palette.low_energy, t * 2.)
+                } else {
+                    palette.low_energy.lerp(palette.high_energy, t * 2. - 1.)
+                }
+            }
+            Self::Energy(false) => {
                 if bot.energy < reproduction_required_energy * 5. {
-                    Color::new(255, 255, 0)
+                    palette.low_energy
                         * (bot.energy as f64 / config.reproduction_required_energy as f64 * 5.)
                 } else {
-                    Color::new(0, 255, 0)
+                    palette.high_energy
                         * ((bot.energy as f64 - reproduction_required_energy as f64 * 5.) / 255.)
                 }
             }
-            Self::Lifetime => Color::new(
-                10 + ((bot.age as f64 / config.cell_max_age as f64) * 245.) as u8,
-                0,
-                0,
-            ),
+            Self::Lifetime => palette
+                .young
+                .lerp(palette.old, bot.age as f64 / config.cell_max_age as f64),
+            // A cell alone is as dense as it gets for a window of 1
+            Self::Density => density_color(1.0),
+            Self::InstructionPointer(blend) => {
+                let brightness = (bot.instruction_pointer() as f64 + 1.) / GENOME_LENGTH as f64;
+                if *blend {
+                    bot.color * brightness
+                } else {
+                    Color::new(255, 255, 255) * brightness
+                }
+            }
         }
     }
 }
+
+/// Fraction of alive cells in the `window` x `window` square around each cell, row by row.
+/// Near the edges of the map only the part of the square inside the map is counted
+pub fn density_field(map: &Map<Bot>, window: usize) -> Vec<f32> {
+    let (width, height) = (map.width(), map.height());
+
+    let mut alive = vec![0.0; width * height];
+    for (x, y, cell) in map.iter() {
+        if cell.alive {
+            alive[y * width + x] = 1.0;
+        }
+    }
+
+    // Box blur is separable, so average the rows first, and then the columns
+    let radius = window / 2;
+    let rows = box_blur(&alive, width, height, radius, (1, width));
+    box_blur(&rows, height, width, radius, (width, 1))
+}
+
+// Average every value with it's neighbors within `radius` along one axis.
+// `step` and `line_step` are the index steps along the blurred axis, and across it
+fn box_blur(
+    values: &[f32],
+    length: usize,
+    lines: usize,
+    radius: usize,
+    (step, line_step): (usize, usize),
+) -> Vec<f32> {
+    let mut blurred = vec![0.0; values.len()];
+
+    for line in 0..lines {
+        for i in 0..length {
+            let span = i.saturating_sub(radius)..=(i + radius).min(length - 1);
+            let count = span.clone().count();
+            let sum: f32 = span.map(|j| values[line * line_step + j * step]).sum();
+
+            blurred[line * line_step + i * step] = sum / count as f32;
+        }
+    }
+
+    blurred
+}
+
+/// Cool-to-warm color of a density from [`density_field`]
+pub fn density_color(density: f32) -> Color {
+    const COOL: Color = Color::new(20, 30, 120);
+    const WARM: Color = Color::new(240, 60, 20);
+
+    COOL.lerp(WARM, density as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(color: Color) -> (u8, u8, u8) {
+        (color.r(), color.g(), color.b())
+    }
+
+    #[test]
+    fn loaded_palette_changes_rendering() {
+        let path = std::env::temp_dir().join(format!(
+            "cell-simulation-{}-palette.json",
+            std::process::id()
+        ));
+        fs::write(&path, r#"{ "old": [0, 0, 255] }"#).unwrap();
+        let palette = Palette::load(&path);
+        fs::remove_file(&path).unwrap();
+        let palette = palette.unwrap();
+
+        let config = Config::default();
+        let mut bot = Bot::new_random(0, 0, &config);
+        bot.age = config.cell_max_age;
+
+        let default = RenderingMode::Lifetime.render(&bot, &config, &Palette::default());
+        let loaded = RenderingMode::Lifetime.render(&bot, &config, &palette);
+        assert_eq!(rgb(default), (255, 0, 0));
+        assert_eq!(rgb(loaded), (0, 0, 255));
+        // Colors missing from the file keep their defaults
+        assert_eq!(rgb(palette.young), rgb(Palette::default().young));
+    }
+}
@@ -1,6 +1,11 @@
+#[cfg(any(feature = "image", feature = "watch-config"))]
+use std::fs;
+#[cfg(feature = "watch-config")]
+use std::time::SystemTime;
 use std::{
+    path::PathBuf,
     sync::{
-        mpsc::{self, Receiver, SendError, Sender, SyncSender},
+        mpsc::{self, Receiver, Sender, SyncSender},
         Arc,
     },
     thread,
@@ -8,16 +13,93 @@ use std::{
 };
 
 use crate::{
-    simulation::{bot::Bot, map::Map, Simulation},
-    Config,
+    error::{Error, Result},
+    simulation::{
+        self,
+        bot::Bot,
+        gene::{Gene, Instruction},
+        map::Map,
+        save::{self, SavedState},
+        stats::Stats,
+        Simulation,
+    },
+    Config, GENOME_LENGTH,
 };
 
+/// Histograms and dominant genome walk through every bot on the map,
+/// so they are recomputed only once per this many metadata updates
+const HISTOGRAM_INTERVAL: usize = 10;
+
+/// With adaptive TPS, the simulation thread sleeps between ticks,
+/// so that ticking takes about this fraction of it's time
+const TICK_TIME_SHARE: f64 = 0.75;
+/// How much each new tick duration moves the average, for adaptive TPS
+const TICK_SMOOTHING: f64 = 0.1;
+/// Shorter sleeps aren't worth it, so small populations run at full speed
+const MIN_ADAPTIVE_SLEEP: Duration = Duration::from_millis(1);
+
+/// How often the watched config file is checked for changes, see [`Cmd::WatchConfig`]
+#[cfg(feature = "watch-config")]
+const CONFIG_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// When a [`Cmd::RunUntil`] run pauses the simulation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopCondition {
+    /// No alive bots are left
+    Extinction,
+    /// Fewer alive bots than this
+    PopulationBelow(usize),
+    /// More alive bots than this
+    PopulationAbove(usize),
+    /// Fewer species than this
+    SpeciesCountBelow(usize),
+    /// Evolution has stopped, see [`Config::stagnation_window`]
+    Stagnation,
+}
+
+impl StopCondition {
+    pub fn is_met(&self, stats: &Stats) -> bool {
+        match *self {
+            Self::Extinction => stats.alive_count == 0,
+            Self::PopulationBelow(count) => stats.alive_count < count,
+            Self::PopulationAbove(count) => stats.alive_count > count,
+            Self::SpeciesCountBelow(count) => stats.species_count < count,
+            Self::Stagnation => stats.stagnant,
+        }
+    }
+}
+
 /// Command from main thread to the simulation thread
 pub enum Cmd {
     TogglePause,
     Reset,
     SelectCell(usize, usize),
-    UpdateConfig(Config),
+    SelectSecondary(usize, usize),
+    /// Freeze or unfreeze the selected bot
+    SetFrozen(bool),
+    SetCell(usize, usize, Box<Bot>),
+    /// Reply with a copy of one cell, without waiting for the next metadata
+    QueryCell(usize, usize, Sender<Option<Bot>>),
+    UpdateConfig(Box<Config>),
+    InjectRandom(usize),
+    /// Place copies of a genome on random empty cells
+    InjectGenome(Box<[Gene; GENOME_LENGTH as usize]>, usize),
+    /// Move all alive bots to random positions, keeping their genomes
+    Reshuffle,
+    /// Lower the TPS as ticks get longer, see [`adaptive_sleep`]
+    SetAdaptiveTps(bool),
+    /// Unpause, and pause again once the condition is met
+    RunUntil(StopCondition),
+    /// Save the full state of the simulation to a file
+    SaveState(PathBuf),
+    /// Replace the simulation with the one saved in a file
+    LoadState(PathBuf),
+    #[cfg(feature = "image")]
+    SetTimelapseDir(PathBuf),
+    /// Reload the config from a file (written by "Save config") whenever it changes.
+    /// `None` stops watching
+    #[cfg(feature = "watch-config")]
+    WatchConfig(Option<PathBuf>),
 }
 
 #[derive(Clone, Default)]
@@ -25,8 +107,20 @@ pub struct SimulationMetadata {
     iterations: usize,
     tps: usize,
     paused: bool,
+    stop_condition: Option<StopCondition>,
+    /// Result of the last save or load
+    save_message: Option<String>,
     map: Map<Bot>,
+    stats: Stats,
+    instruction_histogram: [usize; Instruction::COUNT],
+    energy_histogram: Vec<usize>,
+    dominant_genome: Option<[Gene; GENOME_LENGTH as usize]>,
+    max_energy_cell: Option<(usize, usize)>,
+    resources: Option<Vec<f32>>,
     selected_bot: Option<Bot>,
+    secondary_bot: Option<Bot>,
+    /// See [`Simulation::first_reproduction`], for the lineage of the selected bot
+    selected_lineage_established: Option<usize>,
     config: Config,
 }
 
@@ -51,8 +145,39 @@ pub struct SimulationRunner {
     /// and is revalidated only when sent successfully.
     /// Wrapped in [`Arc`], because [`SyncSender::try_send`] consumes the variable sent.
     next_metadata: Arc<SimulationMetadata>,
+    /// Metadata updates since the instruction histogram was last recomputed
+    histogram_age: usize,
+
+    /// Where the time-lapse frames are written, see [`Config::timelapse_interval`]
+    #[cfg(feature = "image")]
+    timelapse_dir: PathBuf,
+    /// Iterations since the last time-lapse frame
+    #[cfg(feature = "image")]
+    timelapse_age: usize,
+    /// Iterations since the map was last printed, see [`Config::ascii_interval`]
+    ascii_age: usize,
+    /// Iterations since the metadata was last taken by the main thread,
+    /// see [`Config::max_ticks_per_frame`]
+    ticks_since_send: usize,
+
+    /// Config file reloaded whenever it changes, see [`Cmd::WatchConfig`]
+    #[cfg(feature = "watch-config")]
+    watched_config: Option<PathBuf>,
+    /// Modification time of the watched file, when it was last reloaded
+    #[cfg(feature = "watch-config")]
+    watched_config_modified: Option<SystemTime>,
+    #[cfg(feature = "watch-config")]
+    previous_config_check: Instant,
 
     paused: bool,
+    adaptive_tps: bool,
+    /// Moving average of the tick duration, used by the adaptive TPS
+    average_tick: Duration,
+    /// Set by [`Cmd::RunUntil`], cleared when met or when paused by hand
+    stop_condition: Option<StopCondition>,
+    /// State at the start of a [`Cmd::RunUntil`] run, compared with the end state once it's met
+    run_start: Option<SavedState>,
+    save_message: Option<String>,
 
     /// Measuring Ticks Per Second works by storing current amount of iterations in `previous_iterations`,
     /// and after a second, subtract `previous_iterations` from current `iterations`. This way
@@ -68,6 +193,13 @@ pub struct SimulationRunner {
 impl SimulationRunner {
     /// Returns a handle to the thread, [`SimulationHandle`]
     pub fn start_new(simulation: Simulation) -> SimulationHandle {
+        let (runner, handle) = Self::new(simulation);
+        thread::spawn(move || runner.run());
+        handle
+    }
+
+    /// Runner connected to a handle, without a thread to run it
+    fn new(simulation: Simulation) -> (Self, SimulationHandle) {
         let (metadata_tx, metadata_rx) = mpsc::sync_channel(1);
         let (command_tx, command_rx) = mpsc::channel();
 
@@ -75,7 +207,25 @@ impl SimulationRunner {
             rx: command_rx,
             tx: metadata_tx,
             next_metadata: Arc::new(SimulationMetadata::default()),
+            histogram_age: 0,
+            #[cfg(feature = "image")]
+            timelapse_dir: PathBuf::from("timelapse"),
+            #[cfg(feature = "image")]
+            timelapse_age: 0,
+            ascii_age: 0,
+            ticks_since_send: 0,
+            #[cfg(feature = "watch-config")]
+            watched_config: None,
+            #[cfg(feature = "watch-config")]
+            watched_config_modified: None,
+            #[cfg(feature = "watch-config")]
+            previous_config_check: Instant::now(),
             paused: true,
+            adaptive_tps: false,
+            average_tick: Duration::ZERO,
+            stop_condition: None,
+            run_start: None,
+            save_message: None,
             tps: 0,
             previous_iterations: 0,
             previous_tps_check: Instant::now(),
@@ -85,31 +235,90 @@ impl SimulationRunner {
         runner.construct_metadata();
         let metadata = runner.next_metadata.clone();
 
-        thread::spawn(move || runner.run());
-
-        SimulationHandle {
+        let handle = SimulationHandle {
             tx: command_tx,
             rx: metadata_rx,
             metadata,
-        }
+        };
+        (runner, handle)
     }
 
     fn handle_commands(&mut self) {
         if let Ok(command) = self.rx.try_recv() {
-            match command {
-                Cmd::TogglePause => self.paused = !self.paused,
-                Cmd::Reset => {
-                    self.simulation.reset();
-                    self.previous_iterations = 0;
-                    self.tps = 0;
-                    self.previous_tps_check = Instant::now();
-                }
-                Cmd::SelectCell(x, y) => {
-                    let _ = self.simulation.select_bot(x, y);
-                }
-                Cmd::UpdateConfig(config) => {
-                    self.simulation.configuration = config;
+            self.handle_command(command);
+        }
+    }
+    fn handle_command(&mut self, command: Cmd) {
+        match command {
+            Cmd::TogglePause => {
+                self.paused = !self.paused;
+                self.stop_condition = None;
+                self.run_start = None;
+            }
+            Cmd::Reset => {
+                self.simulation.reset();
+                self.previous_iterations = 0;
+                self.tps = 0;
+                self.previous_tps_check = Instant::now();
+                #[cfg(feature = "image")]
+                {
+                    self.timelapse_age = 0;
                 }
+                self.ascii_age = 0;
+            }
+            Cmd::SelectCell(x, y) => {
+                let _ = self.simulation.select_bot(x, y);
+            }
+            Cmd::SelectSecondary(x, y) => {
+                let _ = self.simulation.select_secondary_bot(x, y);
+            }
+            Cmd::SetFrozen(frozen) => {
+                self.simulation.set_selected_frozen(frozen);
+            }
+            Cmd::SetCell(x, y, bot) => {
+                self.simulation.set_cell(x, y, *bot);
+            }
+            Cmd::QueryCell(x, y, reply) => {
+                // The caller might have given up waiting, nothing to do then
+                let _ = reply.send(self.simulation.map().get(x, y).copied());
+            }
+            Cmd::UpdateConfig(config) => {
+                self.simulation.configuration = *config;
+            }
+            Cmd::InjectRandom(count) => {
+                self.simulation.inject_random(count);
+            }
+            Cmd::InjectGenome(genome, count) => {
+                self.simulation.inject_genome(*genome, count);
+            }
+            Cmd::Reshuffle => self.simulation.reshuffle(),
+            Cmd::SetAdaptiveTps(enabled) => self.adaptive_tps = enabled,
+            Cmd::RunUntil(condition) => {
+                self.stop_condition = Some(condition);
+                self.run_start = Some(self.simulation.saved_state());
+                self.paused = false;
+            }
+            Cmd::SaveState(path) => {
+                let result = save::save_to_path(&path, &self.simulation.saved_state());
+                self.save_message = Some(match result {
+                    Ok(()) => format!("Saved to {}", path.display()),
+                    Err(error) => format!("Failed to save to {}: {error}", path.display()),
+                });
+            }
+            Cmd::LoadState(path) => {
+                let result = save::load_from_path(&path).and_then(|state| self.load_state(state));
+                self.save_message = Some(match result {
+                    Ok(()) => format!("Loaded from {}", path.display()),
+                    Err(error) => format!("Failed to load from {}: {error}", path.display()),
+                });
+            }
+            #[cfg(feature = "image")]
+            Cmd::SetTimelapseDir(dir) => self.timelapse_dir = dir,
+            #[cfg(feature = "watch-config")]
+            Cmd::WatchConfig(path) => {
+                self.watched_config = path;
+                // Apply the file on the next check, even if it hasn't changed
+                self.watched_config_modified = None;
             }
         }
     }
@@ -117,19 +326,185 @@ impl SimulationRunner {
         if let Ok(()) = self.tx.try_send(self.next_metadata.clone()) {
             // Compute the next metadata
             self.construct_metadata();
+            self.ticks_since_send = 0;
+        }
+    }
+    fn load_state(&mut self, state: SavedState) -> Result<()> {
+        // The save could have been edited by hand
+        state.config.validate().map_err(Error::InvalidConfig)?;
+
+        // The window and the grid texture are sized for the current map
+        let config = &self.simulation.configuration;
+        if (
+            state.config.width,
+            state.config.height,
+            state.config.cell_size,
+        ) != (config.width, config.height, config.cell_size)
+        {
+            return Err(Error::InvalidConfig(
+                "saved map size doesn't match the current one".to_string(),
+            ));
+        }
+
+        self.simulation = Simulation::from_saved_state(state);
+        self.previous_iterations = self.simulation.iterations();
+        self.tps = 0;
+        self.previous_tps_check = Instant::now();
+        Ok(())
+    }
+
+    /// Reload the watched config file, if it has changed since the last reload.
+    /// Invalid configs are reported and ignored, keeping the current one
+    #[cfg(feature = "watch-config")]
+    fn reload_watched_config(&mut self) {
+        if self.previous_config_check.elapsed() < CONFIG_WATCH_INTERVAL {
+            return;
+        }
+        self.previous_config_check = Instant::now();
+
+        let Some(path) = self.watched_config.clone() else {
+            return;
+        };
+        // The file may not exist yet, or be in the middle of being replaced
+        let Ok(modified) = fs::metadata(&path).and_then(|metadata| metadata.modified()) else {
+            return;
+        };
+        if self.watched_config_modified == Some(modified) {
+            return;
+        }
+        self.watched_config_modified = Some(modified);
+
+        let loaded = save::load_from_path::<Config>(&path).and_then(|mut config| {
+            // Map can't be resized while running
+            let current = &self.simulation.configuration;
+            config.width = current.width;
+            config.height = current.height;
+            config.cell_size = current.cell_size;
+
+            config.validate().map_err(Error::InvalidConfig)?;
+            Ok(config)
+        });
+
+        let message = match loaded {
+            Ok(config) => {
+                self.handle_command(Cmd::UpdateConfig(Box::new(config)));
+                format!("Reloaded config from {}", path.display())
+            }
+            Err(error) => format!("Ignored config in {}: {error}", path.display()),
+        };
+        // Reloads are usually done on headless runs, where the UI message isn't seen
+        eprintln!("{message}");
+        self.save_message = Some(message);
+    }
+
+    /// Print how the population evolved since the [`Cmd::RunUntil`] run started
+    fn print_run_report(&mut self) {
+        if let Some(before) = self.run_start.take() {
+            let after = self.simulation.saved_state();
+            print!("{}", Simulation::compare_snapshots(&before, &after));
         }
     }
+
     fn construct_metadata(&mut self) {
+        let config = &self.simulation.configuration;
+        let (instruction_histogram, energy_histogram, dominant_genome, average_complexity) = if self
+            .histogram_age
+            == 0
+        {
+            (
+                self.simulation.instruction_histogram(),
+                self.simulation
+                    .energy_histogram(config.energy_histogram_buckets, config.energy_histogram_max),
+                self.simulation.dominant_genome(),
+                self.simulation.average_complexity(),
+            )
+        } else {
+            (
+                self.next_metadata.instruction_histogram,
+                self.next_metadata.energy_histogram.clone(),
+                self.next_metadata.dominant_genome,
+                self.next_metadata.stats.average_complexity,
+            )
+        };
+        let mut stats = self.simulation.stats();
+        stats.average_complexity = average_complexity;
+        self.histogram_age = (self.histogram_age + 1) % HISTOGRAM_INTERVAL;
+
         self.next_metadata = Arc::new(SimulationMetadata {
             iterations: self.simulation.iterations(),
             tps: self.tps,
             paused: self.paused,
+            stop_condition: self.stop_condition,
+            save_message: self.save_message.clone(),
             map: self.simulation.map().clone(),
+            stats,
+            instruction_histogram,
+            energy_histogram,
+            dominant_genome,
+            max_energy_cell: self.simulation.max_energy_cell(),
+            resources: self.simulation.resources().map(<[f32]>::to_vec),
             selected_bot: self.simulation.selected_bot(),
+            secondary_bot: self.simulation.secondary_bot(),
+            selected_lineage_established: self
+                .simulation
+                .selected_bot()
+                .and_then(|bot| self.simulation.first_reproduction(bot.lineage)),
             config: self.simulation.configuration,
         });
     }
 
+    /// Save a time-lapse frame, if it is enabled and enough iterations have passed
+    #[cfg(feature = "image")]
+    fn write_timelapse_frame(&mut self) {
+        let Some(interval) = self.simulation.configuration.timelapse_interval else {
+            return;
+        };
+
+        self.timelapse_age += 1;
+        if self.timelapse_age < interval {
+            return;
+        }
+        self.timelapse_age = 0;
+
+        let path = self
+            .timelapse_dir
+            .join(format!("frame_{:08}.png", self.simulation.iterations()));
+        let result = fs::create_dir_all(&self.timelapse_dir)
+            .map_err(|error| error.to_string())
+            .and_then(|()| {
+                self.simulation
+                    .render_to_image()
+                    .save(&path)
+                    .map_err(|error| error.to_string())
+            });
+
+        if let Err(error) = result {
+            eprintln!(
+                "Failed to write time-lapse frame {}: {error}",
+                path.display()
+            );
+        }
+    }
+
+    /// Print the map to the standard output, if it is enabled and enough iterations have passed
+    fn print_ascii_frame(&mut self) {
+        let Some(interval) = self.simulation.configuration.ascii_interval else {
+            return;
+        };
+
+        self.ascii_age += 1;
+        if self.ascii_age < interval {
+            return;
+        }
+        self.ascii_age = 0;
+
+        println!(
+            "Iteration {}:\n{}",
+            self.simulation.iterations(),
+            self.simulation.to_ascii()
+        );
+    }
+
     fn measure_tps(&mut self) {
         if self.previous_tps_check.elapsed().as_millis() > 1000 {
             self.tps = self.simulation.iterations() - self.previous_iterations;
@@ -138,13 +513,53 @@ impl SimulationRunner {
         }
     }
 
+    /// Update the simulation once, along with everything that follows it
+    fn tick(&mut self) {
+        self.ticks_since_send += 1;
+        let tick_start = Instant::now();
+        self.simulation.update();
+        self.measure_tps();
+
+        if self.adaptive_tps {
+            // Smoothed, so that a single slow tick doesn't stall the simulation
+            self.average_tick = self.average_tick.mul_f64(1.0 - TICK_SMOOTHING)
+                + tick_start.elapsed().mul_f64(TICK_SMOOTHING);
+            let sleep = adaptive_sleep(self.average_tick);
+            if sleep >= MIN_ADAPTIVE_SLEEP {
+                thread::sleep(sleep);
+            }
+        }
+
+        if let Some(condition) = self.stop_condition {
+            if condition.is_met(&self.simulation.stats()) {
+                self.paused = true;
+                self.stop_condition = None;
+                self.print_run_report();
+            }
+        }
+
+        #[cfg(feature = "image")]
+        self.write_timelapse_frame();
+        self.print_ascii_frame();
+    }
+
     fn run(mut self) {
         loop {
             self.handle_commands();
+            #[cfg(feature = "watch-config")]
+            self.reload_watched_config();
+
+            let over_budget = self
+                .simulation
+                .configuration
+                .max_ticks_per_frame
+                .is_some_and(|budget| self.ticks_since_send >= budget);
 
-            if !self.paused {
-                self.simulation.update();
-                self.measure_tps();
+            if over_budget && !self.paused {
+                // Yield to the main thread until it takes the next frame
+                thread::sleep(Duration::from_millis(1));
+            } else if !self.paused {
+                self.tick();
             } else {
                 // Sleep for 10ms when paused, to not waste clock cycles
                 thread::sleep(Duration::from_millis(10));
@@ -155,17 +570,68 @@ impl SimulationRunner {
     }
 }
 
+/// How long to sleep after each tick with adaptive TPS. Grows with the tick duration,
+/// so the TPS drops as the population grows, leaving the rest of the CPU time to the UI
+fn adaptive_sleep(average_tick: Duration) -> Duration {
+    average_tick.mul_f64((1.0 - TICK_TIME_SHARE) / TICK_TIME_SHARE)
+}
+
 impl SimulationHandle {
-    pub fn reset(&mut self) -> Result<(), SendError<Cmd>> {
-        self.tx.send(Cmd::Reset)
+    fn send(&self, command: Cmd) -> Result<()> {
+        self.tx.send(command).map_err(Error::from)
+    }
+    // Commands with coordinates are checked here, the simulation thread would ignore them
+    fn check_bounds(&self, x: usize, y: usize) -> Result<()> {
+        let config = self.config();
+        if x < config.width && y < config.height {
+            Ok(())
+        } else {
+            Err(Error::OutOfBounds(x, y))
+        }
     }
 
-    pub fn toggle_pause(&mut self) -> Result<(), SendError<Cmd>> {
-        self.tx.send(Cmd::TogglePause)
+    pub fn reset(&mut self) -> Result<()> {
+        self.send(Cmd::Reset)
+    }
+
+    pub fn toggle_pause(&mut self) -> Result<()> {
+        self.send(Cmd::TogglePause)
     }
     pub fn is_paused(&self) -> bool {
         self.metadata.paused
     }
+
+    /// Unpause the simulation, and pause it again once the condition is met
+    pub fn run_until(&mut self, condition: StopCondition) -> Result<()> {
+        self.send(Cmd::RunUntil(condition))
+    }
+    pub fn run_until_extinction(&mut self) -> Result<()> {
+        self.run_until(StopCondition::Extinction)
+    }
+    pub fn run_until_population_below(&mut self, count: usize) -> Result<()> {
+        self.run_until(StopCondition::PopulationBelow(count))
+    }
+    pub fn run_until_population_above(&mut self, count: usize) -> Result<()> {
+        self.run_until(StopCondition::PopulationAbove(count))
+    }
+    pub fn run_until_species_below(&mut self, count: usize) -> Result<()> {
+        self.run_until(StopCondition::SpeciesCountBelow(count))
+    }
+    pub fn save_state(&mut self, path: PathBuf) -> Result<()> {
+        self.send(Cmd::SaveState(path))
+    }
+    pub fn load_state(&mut self, path: PathBuf) -> Result<()> {
+        self.send(Cmd::LoadState(path))
+    }
+    /// Result of the last [`SimulationHandle::save_state`] or [`SimulationHandle::load_state`]
+    pub fn save_message(&self) -> Option<&str> {
+        self.metadata.save_message.as_deref()
+    }
+
+    /// Condition of the current [`SimulationHandle::run_until`] run
+    pub fn stop_condition(&self) -> Option<StopCondition> {
+        self.metadata.stop_condition
+    }
     pub fn iterations(&self) -> usize {
         self.metadata.iterations
     }
@@ -177,24 +643,402 @@ impl SimulationHandle {
         &self.metadata.map
     }
 
-    pub fn select_bot(&mut self, x: usize, y: usize) -> Result<(), SendError<Cmd>> {
-        self.tx.send(Cmd::SelectCell(x, y))
+    pub fn stats(&self) -> &Stats {
+        &self.metadata.stats
+    }
+
+    /// See [`Simulation::instruction_histogram`]. Refreshed every few frames
+    pub fn instruction_histogram(&self) -> &[usize; Instruction::COUNT] {
+        &self.metadata.instruction_histogram
+    }
+
+    /// See [`Simulation::energy_histogram`]. Refreshed every few frames,
+    /// bucket count and range are taken from the config
+    pub fn energy_histogram(&self) -> &[usize] {
+        &self.metadata.energy_histogram
+    }
+
+    /// See [`Simulation::dominant_genome`]. Refreshed every few frames
+    pub fn dominant_genome(&self) -> Option<&[Gene; GENOME_LENGTH as usize]> {
+        self.metadata.dominant_genome.as_ref()
+    }
+
+    /// See [`Simulation::max_energy_cell`]
+    pub fn max_energy_cell(&self) -> Option<(usize, usize)> {
+        self.metadata.max_energy_cell
+    }
+
+    /// See [`Simulation::resources`]
+    pub fn resources(&self) -> Option<&[f32]> {
+        self.metadata.resources.as_deref()
+    }
+
+    /// See [`Simulation::living_bots`]. Walks the map from the latest metadata
+    pub fn living_bots(&self) -> impl Iterator<Item = (usize, usize, &Bot)> {
+        simulation::living_bots(&self.metadata.map)
+    }
+
+    /// See [`Simulation::find_by_lineage`]
+    pub fn find_by_lineage(&self, lineage: u64) -> Vec<(usize, usize)> {
+        simulation::find_by_lineage(&self.metadata.map, lineage)
+    }
+
+    pub fn select_bot(&mut self, x: usize, y: usize) -> Result<()> {
+        self.check_bounds(x, y)?;
+        self.send(Cmd::SelectCell(x, y))
+    }
+    /// Select a second bot, to compare it's genome against the selected one
+    pub fn select_secondary_bot(&mut self, x: usize, y: usize) -> Result<()> {
+        self.check_bounds(x, y)?;
+        self.send(Cmd::SelectSecondary(x, y))
+    }
+    /// Freeze or unfreeze the selected bot. Applied to the bot on the map,
+    /// so it works even if the bot has moved since the last metadata
+    pub fn set_selected_frozen(&mut self, frozen: bool) -> Result<()> {
+        self.send(Cmd::SetFrozen(frozen))
     }
+    /// Current state of one cell, straight from the simulation thread.
+    /// Blocks until the command is handled, which is cheaper than waiting for a copy of the map
+    pub fn query_cell(&self, x: usize, y: usize) -> Result<Option<Bot>> {
+        self.check_bounds(x, y)?;
+        let (reply_tx, reply_rx) = mpsc::channel();
+        self.send(Cmd::QueryCell(x, y, reply_tx))?;
+        Ok(reply_rx.recv()?)
+    }
+    /// Replace a cell on the map. Used by the paint tools
+    pub fn set_cell(&mut self, x: usize, y: usize, bot: Bot) -> Result<()> {
+        self.check_bounds(x, y)?;
+        self.send(Cmd::SetCell(x, y, Box::new(bot)))
+    }
+
     pub fn selected_bot(&self) -> Option<&Bot> {
         self.metadata.selected_bot.as_ref()
     }
+    pub fn secondary_bot(&self) -> Option<&Bot> {
+        self.metadata.secondary_bot.as_ref()
+    }
+    /// Iteration on which the lineage of the selected bot first reproduced
+    pub fn selected_lineage_established(&self) -> Option<usize> {
+        self.metadata.selected_lineage_established
+    }
 
     pub fn config(&self) -> &Config {
         &self.metadata.config
     }
-    pub fn update_config(&mut self, config: Config) -> Result<(), SendError<Cmd>> {
-        self.tx.send(Cmd::UpdateConfig(config))
+    /// Fails without sending anything, if the config doesn't pass [`Config::validate`]
+    pub fn update_config(&mut self, config: Config) -> Result<()> {
+        config.validate().map_err(Error::InvalidConfig)?;
+        self.send(Cmd::UpdateConfig(Box::new(config)))
+    }
+    /// Place up to `count` random bots on random empty cells
+    pub fn inject_random(&mut self, count: usize) -> Result<()> {
+        self.send(Cmd::InjectRandom(count))
+    }
+    /// See [`Simulation::inject_genome`]
+    pub fn inject_genome(
+        &mut self,
+        genome: [Gene; GENOME_LENGTH as usize],
+        count: usize,
+    ) -> Result<()> {
+        self.send(Cmd::InjectGenome(Box::new(genome), count))
+    }
+    /// Lower the TPS automatically as ticks get longer, to keep the UI responsive
+    pub fn set_adaptive_tps(&mut self, enabled: bool) -> Result<()> {
+        self.send(Cmd::SetAdaptiveTps(enabled))
+    }
+    /// See [`Simulation::reshuffle`]
+    pub fn reshuffle(&mut self) -> Result<()> {
+        self.send(Cmd::Reshuffle)
+    }
+    /// Change where the time-lapse frames are written
+    #[cfg(feature = "image")]
+    pub fn set_timelapse_dir(&mut self, dir: PathBuf) -> Result<()> {
+        self.send(Cmd::SetTimelapseDir(dir))
+    }
+
+    /// Reload the config from a file whenever it changes, or stop with `None`.
+    /// See [`Cmd::WatchConfig`]
+    #[cfg(feature = "watch-config")]
+    pub fn watch_config(&mut self, path: Option<PathBuf>) -> Result<()> {
+        self.send(Cmd::WatchConfig(path))
     }
 
-    // Receive metadata update from the thread
-    pub fn update(&mut self) {
+    // Receive metadata update from the thread.
+    // Returns whether new metadata has arrived
+    pub fn update(&mut self) -> bool {
         if let Ok(metadata) = self.rx.try_recv() {
             self.metadata = metadata;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::save::SavedState;
+
+    // Bots on the left edge of an 8x6 map facing right, each running one instruction
+    fn population(bots: &[(Instruction, f32)]) -> Simulation {
+        let config = Config {
+            width: 8,
+            height: 6,
+            initial_direction: Some(simulation::direction::Direction::Right),
+            ..Default::default()
+        };
+        let cells = bots
+            .iter()
+            .enumerate()
+            .map(|(y, &(instruction, energy))| {
+                let genome = [Gene {
+                    instruction,
+                    ..Default::default()
+                }; GENOME_LENGTH as usize];
+                let mut bot = Bot::new_with_genome(0, y, genome, &config);
+                bot.energy = energy;
+                bot
+            })
+            .collect();
+        Simulation::from_saved_state(SavedState {
+            config,
+            iterations: 0,
+            cells,
+            resources: Vec::new(),
+        })
+    }
+
+    // Iterations after which the run paused itself, or None if it was still running after 20
+    fn run_until(simulation: Simulation, condition: StopCondition) -> Option<usize> {
+        let (mut runner, _handle) = SimulationRunner::new(simulation);
+        runner.handle_command(Cmd::RunUntil(condition));
+        for _ in 0..20 {
+            runner.tick();
+            if runner.paused {
+                return Some(runner.simulation.iterations());
+            }
+        }
+        None
+    }
+
+    // Bots that die of noop cost on the first tick, and bots that live on
+    const DYING: (Instruction, f32) = (Instruction::Noop, 0.05);
+    const LIVING: (Instruction, f32) = (Instruction::Photosynthesis, 100.0);
+
+    #[test]
+    fn runs_until_extinction() {
+        assert_eq!(
+            run_until(population(&[DYING, DYING]), StopCondition::Extinction),
+            Some(1)
+        );
+        assert_eq!(
+            run_until(population(&[DYING, LIVING]), StopCondition::Extinction),
+            None
+        );
+    }
+
+    #[test]
+    fn runs_until_population_below() {
+        let bots = [DYING, DYING, LIVING, LIVING];
+        assert_eq!(
+            run_until(population(&bots), StopCondition::PopulationBelow(3)),
+            Some(1)
+        );
+        assert_eq!(
+            run_until(population(&bots), StopCondition::PopulationBelow(2)),
+            None
+        );
+    }
+
+    #[test]
+    fn runs_until_population_above() {
+        // Each of them has a child on the first tick
+        let parent = (Instruction::MakeChild, 100.0);
+        let bots = [parent, parent, parent];
+        assert_eq!(
+            run_until(population(&bots), StopCondition::PopulationAbove(5)),
+            Some(1)
+        );
+        assert_eq!(
+            run_until(population(&[LIVING]), StopCondition::PopulationAbove(1)),
+            None
+        );
+    }
+
+    #[test]
+    fn runs_until_species_count_below() {
+        // The photosynthesizers outlive the other species
+        let bots = [DYING, DYING, LIVING];
+        assert_eq!(
+            run_until(population(&bots), StopCondition::SpeciesCountBelow(2)),
+            Some(1)
+        );
+        assert_eq!(
+            run_until(population(&bots), StopCondition::SpeciesCountBelow(1)),
+            None
+        );
+    }
+
+    // Update the handle until the condition holds, or give up after a few seconds
+    #[cfg(feature = "watch-config")]
+    fn wait_for(
+        handle: &mut SimulationHandle,
+        condition: impl Fn(&SimulationHandle) -> bool,
+    ) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            handle.update();
+            if condition(handle) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    #[cfg(feature = "watch-config")]
+    #[test]
+    fn watched_config_is_reloaded_on_change() {
+        let config = Config {
+            width: 10,
+            height: 10,
+            ..Default::default()
+        };
+        let path = std::env::temp_dir().join(format!(
+            "cell-simulation-{}-watched_config.json",
+            std::process::id()
+        ));
+        let mut handle = SimulationRunner::start_new(Simulation::new(Some(config)));
+
+        let first = Config {
+            mutation_percent: 3.0,
+            ..config
+        };
+        save::save_to_path(&path, &first).unwrap();
+        handle.watch_config(Some(path.clone())).unwrap();
+        assert!(wait_for(&mut handle, |handle| handle
+            .config()
+            .mutation_percent
+            == 3.0));
+
+        let second = Config {
+            mutation_percent: 7.0,
+            ..config
+        };
+        save::save_to_path(&path, &second).unwrap();
+        // The file may be rewritten within the file system's timestamp precision
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now() + Duration::from_secs(10)))
+            .unwrap();
+        let reloaded = wait_for(&mut handle, |handle| {
+            handle.config().mutation_percent == 7.0
+        });
+        fs::remove_file(&path).unwrap();
+
+        assert!(reloaded);
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn timelapse_writes_a_frame_every_interval() {
+        let dir =
+            std::env::temp_dir().join(format!("cell-simulation-{}-timelapse", std::process::id()));
+        let config = Config {
+            width: 10,
+            height: 10,
+            timelapse_interval: Some(25),
+            ..Default::default()
+        };
+        let (mut runner, _handle) = SimulationRunner::new(Simulation::new(Some(config)));
+        runner.timelapse_dir = dir.clone();
+
+        for _ in 0..100 {
+            runner.tick();
         }
+
+        let mut frames: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        frames.sort();
+        assert_eq!(
+            frames,
+            [
+                "frame_00000025.png",
+                "frame_00000050.png",
+                "frame_00000075.png",
+                "frame_00000100.png"
+            ]
+        );
+    }
+
+    #[test]
+    fn stopped_thread_closes_the_channel() {
+        let (runner, mut handle) = SimulationRunner::new(population(&[LIVING]));
+        drop(runner);
+
+        assert!(matches!(handle.toggle_pause(), Err(Error::ChannelClosed)));
+        assert!(matches!(handle.reset(), Err(Error::ChannelClosed)));
+    }
+
+    #[test]
+    fn cells_outside_of_the_map_are_rejected() {
+        let (_runner, mut handle) = SimulationRunner::new(population(&[LIVING]));
+
+        assert!(matches!(
+            handle.select_bot(8, 0),
+            Err(Error::OutOfBounds(8, 0))
+        ));
+        assert!(matches!(
+            handle.query_cell(0, 6),
+            Err(Error::OutOfBounds(0, 6))
+        ));
+    }
+
+    #[test]
+    fn invalid_config_is_rejected() {
+        let (_runner, mut handle) = SimulationRunner::new(population(&[LIVING]));
+        let config = Config {
+            mutation_percent: 200.0,
+            ..*handle.config()
+        };
+
+        assert!(matches!(
+            handle.update_config(config),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn query_returns_the_placed_bot() {
+        let mut handle = SimulationRunner::start_new(population(&[LIVING]));
+        let config = *handle.config();
+        let mut genome = [Gene::default(); GENOME_LENGTH as usize];
+        genome[3].instruction = Instruction::AttackCell;
+        let mut placed = Bot::new_with_genome(0, 0, genome, &config);
+        placed.energy = 42.0;
+
+        handle.set_cell(5, 4, placed).unwrap();
+        let queried = handle.query_cell(5, 4).unwrap().unwrap();
+
+        assert_eq!(queried.coordinates(), (5, 4));
+        assert_eq!(queried.genome, genome);
+        assert_eq!(queried.energy, 42.0);
+        assert!(handle
+            .query_cell(6, 4)
+            .unwrap()
+            .is_some_and(|cell| cell.empty));
+    }
+
+    #[test]
+    fn query_fails_once_the_thread_has_stopped() {
+        let (runner, handle) = SimulationRunner::new(population(&[LIVING]));
+        drop(runner);
+
+        assert!(matches!(handle.query_cell(0, 0), Err(Error::ChannelClosed)));
     }
 }
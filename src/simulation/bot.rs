@@ -1,27 +1,107 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use super::color::Color;
 use super::config;
+use super::config::{DeathMode, KinCompareMode, NewbornFacing};
 use super::direction::Direction;
 use super::gene;
 use super::gene::Gene;
 use super::map::Map;
-use crate::{Config, GENOME_LENGTH};
+use super::stats::ActionTally;
+use crate::Config;
+
+// How many last executed instructions are remembered for debugging
+const TRACE_LENGTH: usize = 16;
+
+/// Ring buffer with indices of the last executed instructions
+#[derive(Copy, Clone, Default, Serialize, Deserialize)]
+pub struct InstructionTrace {
+    indices: [u8; TRACE_LENGTH],
+    // Where the next index will be written
+    next: usize,
+    len: usize,
+}
+
+impl InstructionTrace {
+    fn record(&mut self, index: u8) {
+        self.indices[self.next] = index;
+        self.next = (self.next + 1) % TRACE_LENGTH;
+        self.len = (self.len + 1).min(TRACE_LENGTH);
+    }
+
+    /// Recorded instruction indices, from the oldest to the latest
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        let start = (self.next + TRACE_LENGTH - self.len) % TRACE_LENGTH;
+        (0..self.len).map(move |i| self.indices[(start + i) % TRACE_LENGTH])
+    }
 
-#[derive(Copy, Clone)]
+    /// Index of the latest executed instruction
+    pub fn last(&self) -> Option<u8> {
+        self.iter().last()
+    }
+}
+
+/// Link between the two cells of a body, see [`Config::multicell`].
+/// The head is a regular bot, the tail is a copy of it that never acts on it's own.
+/// Attacks and energy given to the tail go to the head, and the tail dies with it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BodyLink {
+    /// Stored on the head, coordinates of it's tail
+    Tail(usize, usize),
+    /// Stored on the tail, coordinates of it's head
+    Head(usize, usize),
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct Bot {
     pub alive: bool,
     pub empty: bool,
+    // Walls are obstacles: they never update, block movement and reproduction,
+    // and can't be attacked or recycled
+    pub wall: bool,
 
     pub x: usize,
     pub y: usize,
     pub energy: f32,
+    // Energy at the start of the last update, to see how much the bot spends per tick
+    pub energy_last_tick: f32,
     pub direction: Direction,
     pub color: Color,
     pub age: u32,
+    // Personal deviation from `Config::cell_max_age`, rolled at birth
+    pub age_offset: i32,
+    // Ticks since the bot was born or last reproduced, see `Config::reproduction_cooldown`
+    pub ticks_since_reproduction: u32,
+    // Random ID of the bot that started the family, inherited by all descendants
+    pub lineage: u64,
+    // Frozen bots skip their updates, while the rest of the world keeps running.
+    // Used to inspect a bot, never inherited by children
+    #[serde(default)]
+    pub frozen: bool,
+    // Whether the last MoveForwards, AttackCell or MakeChild couldn't be done.
+    // Not a part of the genome, and never inherited by children
+    #[serde(default)]
+    pub last_action_failed: bool,
+    // Other cell of the body, if the bot has grown a tail or is one
+    #[serde(default)]
+    pub body: Option<BodyLink>,
+    // Age until which attacks on the bot do nothing, see `Config::newborn_invulnerable_ticks`
+    #[serde(default)]
+    pub invulnerable_until: u32,
 
     pub genome: [Gene; config::GENOME_LENGTH as usize],
     current_instruction: u8,
+    // Cached hash of the genome's instructions, to make relative checks cheap.
+    // Must be refreshed with `refresh_fingerprint()` whenever instructions change,
+    // or after the bot is loaded from a save
+    #[serde(skip)]
+    instruction_fingerprint: u64,
+    // Last executed instructions. Not a part of the genome, so relative checks ignore it
+    trace: InstructionTrace,
 }
 
 impl std::fmt::Debug for Bot {
@@ -31,53 +111,106 @@ impl std::fmt::Debug for Bot {
             .field("y", &self.y)
             .field("alive", &self.alive)
             .field("empty", &self.empty)
+            .field("wall", &self.wall)
             .field("energy", &self.energy)
             .field("direction", &self.direction)
+            .field("lineage", &self.lineage)
             .finish()
     }
 }
 
 impl Default for Bot {
     fn default() -> Self {
-        Bot {
+        let mut bot = Bot {
             alive: false,
             empty: true,
+            wall: false,
 
             x: 0,
             y: 0,
             energy: 0.0,
+            energy_last_tick: 0.0,
             direction: Direction::Left,
             age: 0,
+            age_offset: 0,
+            ticks_since_reproduction: 0,
+            lineage: 0,
+            frozen: false,
+            last_action_failed: false,
+            body: None,
+            invulnerable_until: 0,
 
             color: Color::BLACK,
             genome: [Gene::default(); config::GENOME_LENGTH as usize],
             current_instruction: 0,
-        }
+            instruction_fingerprint: 0,
+            trace: InstructionTrace::default(),
+        };
+        bot.refresh_fingerprint();
+        bot
     }
 }
 
 impl Bot {
     // Generates an alive bot with random color and genome
     pub fn new_random(x: usize, y: usize, config: &Config) -> Self {
-        let mut genome = [Gene::default(); config::GENOME_LENGTH as usize];
-        for i in 0..GENOME_LENGTH {
-            genome[i as usize] = Gene::new_random(config);
-        }
+        Self::new_random_with_rng(&mut thread_rng(), x, y, config)
+    }
+    // Same as `new_random()`, but takes the randomness from given generator
+    pub fn new_random_with_rng(rng: &mut impl Rng, x: usize, y: usize, config: &Config) -> Self {
+        let genome = gene::random_genome(rng, config);
+        let mut bot = Self::new_with_genome_and_rng(rng, x, y, genome, config);
+        bot.energy = Self::random_start_energy(rng, config);
+        bot.energy_last_tick = bot.energy;
+        bot
+    }
 
-        Bot {
+    // Generates an alive bot with given genome, random color and `start_energy`
+    pub fn new_with_genome(
+        x: usize,
+        y: usize,
+        genome: [Gene; config::GENOME_LENGTH as usize],
+        config: &Config,
+    ) -> Self {
+        Self::new_with_genome_and_rng(&mut thread_rng(), x, y, genome, config)
+    }
+    // Same as `new_with_genome()`, but takes the randomness from given generator
+    pub fn new_with_genome_and_rng(
+        rng: &mut impl Rng,
+        x: usize,
+        y: usize,
+        genome: [Gene; config::GENOME_LENGTH as usize],
+        config: &Config,
+    ) -> Self {
+        let mut bot = Bot {
             alive: true,
             empty: false,
+            wall: false,
 
             x,
             y,
             energy: config.start_energy,
-            direction: Direction::generate_random(),
+            energy_last_tick: config.start_energy,
+            direction: config
+                .initial_direction
+                .unwrap_or_else(|| Direction::generate_random_with_rng(rng)),
             age: 0,
+            age_offset: Self::random_age_offset(rng, config),
+            ticks_since_reproduction: 0,
+            lineage: rng.gen(),
+            frozen: false,
+            last_action_failed: false,
+            body: None,
+            invulnerable_until: 0,
 
-            color: random(),
+            color: rng.gen(),
             genome,
             current_instruction: 0,
-        }
+            instruction_fingerprint: 0,
+            trace: InstructionTrace::default(),
+        };
+        bot.refresh_fingerprint();
+        bot
     }
 
     // Generates an empty bot
@@ -89,6 +222,17 @@ impl Bot {
         }
     }
 
+    // Generates a wall
+    pub fn new_wall(x: usize, y: usize) -> Self {
+        Bot {
+            empty: false,
+            wall: true,
+            x,
+            y,
+            ..Default::default()
+        }
+    }
+
     pub fn x(&self) -> usize {
         self.x
     }
@@ -103,6 +247,37 @@ impl Bot {
         self.y = y;
     }
 
+    // Random offset in range `-max_age_jitter..=max_age_jitter`
+    fn random_age_offset(rng: &mut impl Rng, config: &Config) -> i32 {
+        let jitter = i32::try_from(config.max_age_jitter).unwrap_or(i32::MAX);
+        rng.gen_range(-jitter..=jitter)
+    }
+    // Starting energy of a bot generated with the map
+    fn random_start_energy(rng: &mut impl Rng, config: &Config) -> f32 {
+        match config.start_energy_range {
+            Some((min, max)) if min <= max => rng.gen_range(min..=max),
+            _ => config.start_energy,
+        }
+    }
+
+    // Age at which this bot dies
+    pub fn max_age(&self, config: &Config) -> u32 {
+        config.cell_max_age.saturating_add_signed(self.age_offset)
+    }
+
+    // Kill the bot. Depending on `Config::death_mode`, it either leaves a corpse, or vanishes
+    pub fn die(&mut self, config: &Config) {
+        self.alive = false;
+
+        if config.death_mode == DeathMode::Vanish {
+            self.empty = true;
+            // Otherwise the simulation hands the energy out, see `Simulation::release_energy`
+            if !config.death_energy_to_neighbors {
+                self.energy = 0.0;
+            }
+        }
+    }
+
     // Whether a bot should update
     pub fn should_update(&self) -> bool {
         self.alive
@@ -112,77 +287,460 @@ impl Bot {
     pub fn current_instruction(&self) -> &Gene {
         &self.genome[self.current_instruction as usize]
     }
+    // Index of the gene that will be executed next
+    pub fn instruction_pointer(&self) -> u8 {
+        self.current_instruction
+    }
+    // Instruction that is actually executed. Disabled instructions
+    // (e.g. from a save made with a different config) act as `Noop`
+    fn effective_instruction(&self, config: &Config) -> gene::Instruction {
+        let instruction = self.current_instruction().instruction;
+        if config.enabled_instructions.contains(instruction) {
+            instruction
+        } else {
+            gene::Instruction::Noop
+        }
+    }
+
+    // Indices of the last executed instructions
+    pub fn trace(&self) -> &InstructionTrace {
+        &self.trace
+    }
 
     // Whether a bot is a dead cell
     pub fn is_dead(&self) -> bool {
-        !self.alive && !self.empty
+        !self.alive && !self.empty && !self.wall
+    }
+
+    // Whether attacks on the bot do nothing, because it was born too recently
+    pub fn is_invulnerable(&self) -> bool {
+        self.age < self.invulnerable_until
+    }
+
+    // Whether this is the tail of a body, which doesn't act on it's own
+    pub fn is_tail(&self) -> bool {
+        matches!(self.body, Some(BodyLink::Head(..)))
+    }
+
+    // Coordinates of the bot's tail, if it has one
+    pub fn tail(&self) -> Option<(usize, usize)> {
+        match self.body {
+            Some(BodyLink::Tail(x, y)) => Some((x, y)),
+            _ => None,
+        }
+    }
+
+    // Whether a bot can move or reproduce into this cell.
+    // Corpses are passable only if `Config::corpses_block` is disabled, and get destroyed then
+    pub fn is_passable(&self, config: &Config) -> bool {
+        self.empty || (!config.corpses_block && self.is_dead())
+    }
+
+    // Cached `genome_fingerprint(KinCompareMode::InstructionsOnly)`
+    pub fn instruction_fingerprint(&self) -> u64 {
+        self.instruction_fingerprint
+    }
+    // Recompute the cached instruction fingerprint after the genome was changed
+    pub fn refresh_fingerprint(&mut self) {
+        self.instruction_fingerprint = self.genome_fingerprint(KinCompareMode::InstructionsOnly);
+    }
+
+    // Hash of the genome, the same for the same genomes across runs.
+    // Depending on the mode, either only instructions or all the fields of genes are hashed.
+    // Everything that identifies species should use this, so that they agree with each other
+    pub fn genome_fingerprint(&self, mode: KinCompareMode) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for gene in self.genome.iter() {
+            gene.instruction.hash(&mut hasher);
+
+            if mode == KinCompareMode::FullGene {
+                gene.option.hash(&mut hasher);
+                gene.energy.to_bits().hash(&mut hasher);
+                gene.branch.hash(&mut hasher);
+                gene.branch_alt.hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    }
+
+    // Whether the other bot is a relative, i.e. has all the same genes.
+    // Depending on the mode, either only instructions or all the fields of genes are checked
+    pub fn is_relative(&self, other: &Bot, mode: KinCompareMode) -> bool {
+        // Fingerprints rule out most non-relatives without comparing whole genomes,
+        // the full comparison guards against hash collisions
+        self.instruction_fingerprint == other.instruction_fingerprint
+            && self
+                .genome
+                .iter()
+                .zip(other.genome.iter())
+                .all(|(ours, theirs)| match mode {
+                    KinCompareMode::InstructionsOnly => ours.instruction == theirs.instruction,
+                    KinCompareMode::FullGene => ours == theirs,
+                })
     }
 
     // Update a bot
     // Bot needs a mutable reference to the map to be able to look up other bots and change their fields
     // Example: Attacking other bots (changing their energy), or schecking the bot in front
-    pub fn update(&mut self, map: &mut Map<Self>, config: &Config) {
-        if !self.alive {
+    // Successful actions are counted in the `tally`, and randomness comes from `rng`.
+    // Returns the bot whose cell was taken by a child, see `Config::reproduction_overtakes`.
+    // It is already killed, but the rest of it's death is up to the caller
+    pub fn update(
+        &mut self,
+        map: &mut Map<Self>,
+        config: &Config,
+        tally: &mut ActionTally,
+        rng: &mut impl Rng,
+    ) -> Option<Bot> {
+        let mut displaced = None;
+        if !self.alive || self.frozen {
+            return displaced;
+        }
+        self.energy_last_tick = self.energy;
+
+        // Bot keeps executing instructions until it takes an action, or runs out of the budget.
+        // The budget also stops genomes that loop through conditional instructions forever
+        for _ in 0..config.instructions_per_tick.max(1) {
+            let instruction = self.effective_instruction(config);
+            if let Some(bot) = self.execute_instruction(map, config, tally, rng) {
+                displaced = Some(bot);
+            }
+
+            if instruction.is_action() || self.energy < 0.0 {
+                break;
+            }
+        }
+
+        self.energy += config.ambient_energy_per_tick;
+        self.energy -= config.noop_cost;
+        self.energy -= config.min_energy_upkeep;
+        // Cell can die of age, or if it has less than 0 energy
+        if self.age > self.max_age(config) || self.energy < 0.0 {
+            self.die(config);
+        }
+
+        self.age += 1;
+        self.ticks_since_reproduction = self.ticks_since_reproduction.saturating_add(1);
+        displaced
+    }
+
+    // Attack every alive cell within `Config::attack_range`, splitting `attack_energy` between them
+    fn attack_area(
+        &mut self,
+        map: &mut Map<Self>,
+        config: &Config,
+        tally: &mut ActionTally,
+        rng: &mut impl Rng,
+    ) {
+        if self.energy < config.attack_required_energy() {
+            return;
+        }
+
+        let (kin, mut targets): (Vec<_>, Vec<_>) = map
+            .neighbors_within(
+                self.x,
+                self.y,
+                config.attack_range,
+                config.wrap_x,
+                config.wrap_y,
+            )
+            // Tails are skipped, the whole body is affected through the head.
+            // Protected newborns are left alone, as if they weren't there
+            .filter(|&(x, y)| {
+                map.get(x, y)
+                    .is_some_and(|cell| cell.alive && !cell.is_tail() && !cell.is_invulnerable())
+            })
+            .partition(|&(x, y)| self.is_relative(map.get(x, y).unwrap(), config.kin_compare_mode));
+
+        // Every relative in the area backfires on the attacker
+        if config.kin_attack_penalty > 0.0 {
+            self.energy -= config.kin_attack_penalty * kin.len() as f32;
+        } else if config.attack_kin {
+            targets.extend(kin);
+        }
+        if targets.is_empty() {
             return;
         }
 
+        self.energy -= config.attack_required_energy();
+        let share = config.attack_energy / targets.len() as f32;
+        for &(x, y) in targets.iter() {
+            let cell = map.get_mut(x, y).unwrap();
+            let taken_energy = f32::min(cell.energy, share);
+            cell.energy -= taken_energy;
+            self.energy += taken_energy;
+        }
+        tally.attacked += 1;
+
+        if config.attack_steals_gene {
+            let &(x, y) = targets.choose(rng).unwrap();
+            let index = rng.gen_range(0..self.genome.len());
+            self.genome[index] = map.get(x, y).unwrap().genome[index];
+            self.refresh_fingerprint();
+        }
+    }
+
+    // Split the energy of the current gene equally between all alive neighbors
+    fn share_with_neighbors(
+        &mut self,
+        map: &mut Map<Self>,
+        config: &Config,
+        tally: &mut ActionTally,
+    ) {
+        let neighbors: Vec<(usize, usize)> = map
+            .neighbors(self.x, self.y, config.wrap_x, config.wrap_y)
+            // Tails are skipped, the whole body is affected through the head
+            .filter(|&(x, y)| {
+                map.get(x, y)
+                    .is_some_and(|cell| cell.alive && !cell.is_tail())
+            })
+            .collect();
+        if neighbors.is_empty() {
+            return;
+        }
+
+        // Energy can already be negative, if the bot is about to die
+        let energy_to_give = self
+            .current_instruction()
+            .energy
+            .clamp(0.0, self.energy.max(0.0));
+        let share = energy_to_give / neighbors.len() as f32;
+        for &(x, y) in neighbors.iter() {
+            map.get_mut(x, y).unwrap().energy += share;
+        }
+        self.energy -= energy_to_give;
+        tally.gave_energy += 1;
+    }
+
+    // Move the tail into the cell the head has just left
+    fn drag_tail(&mut self, map: &mut Map<Self>, previous: (usize, usize)) {
+        let Some((tail_x, tail_y)) = self.tail() else {
+            return;
+        };
+        // The map still holds the head at it's previous position
+        if !is_attached(map, previous, (tail_x, tail_y)) {
+            self.body = None;
+            return;
+        }
+
+        let mut tail = *map.get(tail_x, tail_y).unwrap();
+        map.set(tail_x, tail_y, Self::new_empty(tail_x, tail_y));
+        tail.set_coordinates(previous.0, previous.1);
+        tail.body = Some(BodyLink::Head(self.x, self.y));
+        map.set(previous.0, previous.1, tail);
+        self.body = Some(BodyLink::Tail(previous.0, previous.1));
+    }
+
+    // Execute the current instruction, and move the instruction pointer.
+    // Returns the displaced bot, like `update()`
+    fn execute_instruction(
+        &mut self,
+        map: &mut Map<Self>,
+        config: &Config,
+        tally: &mut ActionTally,
+        rng: &mut impl Rng,
+    ) -> Option<Bot> {
+        self.trace.record(self.current_instruction);
+        let mut displaced = None;
+
         let mut next_instruction = self.current_instruction + 1;
-        let (looking_x, looking_y) = self.direction.apply_direction(self.x, self.y, config);
+        let looking = self.direction.apply_direction(self.x, self.y, config);
 
-        let cell_in_front = map.get_mut(looking_x, looking_y).unwrap();
+        // A tail stands in for it's head, so that the whole body is affected
+        let target = looking.map(|(looking_x, looking_y)| {
+            match map.get(looking_x, looking_y).unwrap().body {
+                Some(BodyLink::Head(x, y)) if is_attached(map, (x, y), (looking_x, looking_y)) => {
+                    (x, y)
+                }
+                _ => (looking_x, looking_y),
+            }
+        });
+        let facing_own_body = target == Some(self.coordinates());
+        // Beyond an edge that doesn't wrap, the bot faces a wall
+        let mut edge = Self::new_wall(self.x, self.y);
+        let cell_in_front = match target {
+            Some((x, y)) => map.get_mut(x, y).unwrap(),
+            None => &mut edge,
+        };
 
         use gene::Instruction;
-        match self.current_instruction().instruction {
+        let instruction = self.effective_instruction(config);
+        if instruction.is_check() {
+            self.energy -= config.sense_cost;
+        }
+        // Actions that can fail are counted in the tally only when they succeed
+        let succeeded_before = tally.moved + tally.attacked + tally.reproduced;
+        match instruction {
             Instruction::TurnLeft => {
                 self.direction = self.direction.left();
-                self.energy -= config.turn_cost();
+                self.energy -= config.turn_cost;
             }
             Instruction::TurnRight => {
                 self.direction = self.direction.right();
-                self.energy -= config.turn_cost();
+                self.energy -= config.turn_cost;
             }
             Instruction::MoveForwards => {
-                if cell_in_front.empty {
+                if let Some((looking_x, looking_y)) =
+                    looking.filter(|_| cell_in_front.is_passable(config))
+                {
+                    let previous = self.coordinates();
+                    self.energy -= config.phototaxis_tax(previous, (looking_x, looking_y));
                     self.x = looking_x;
                     self.y = looking_y;
                     self.energy -= config.movement_cost;
+                    tally.moved += 1;
+                    self.drag_tail(map, previous);
+                }
+            }
+            Instruction::Leap => {
+                let (mut x, mut y) = self.coordinates();
+                // The tail can't follow a leap
+                let distance = if self.tail().is_some() {
+                    0
+                } else {
+                    self.current_instruction().branch
+                };
+                for _ in 0..distance {
+                    let Some((next_x, next_y)) = self.direction.apply_direction(x, y, config)
+                    else {
+                        break;
+                    };
+                    if !map
+                        .get(next_x, next_y)
+                        .is_some_and(|cell| cell.is_passable(config))
+                    {
+                        break;
+                    }
+
+                    (x, y) = (next_x, next_y);
+                    self.energy -= config.movement_cost;
+                }
+
+                if (x, y) != self.coordinates() {
+                    self.energy -= config.phototaxis_tax(self.coordinates(), (x, y));
+                    tally.moved += 1;
+                }
+                self.set_coordinates(x, y);
+            }
+            Instruction::Grow => {
+                let behind = self
+                    .direction
+                    .opposite()
+                    .apply_direction(self.x, self.y, config);
+                // On an axis one cell long, the cell behind is the bot itself
+                if let Some((behind_x, behind_y)) = behind.filter(|&(x, y)| {
+                    config.multicell
+                        && self.body.is_none()
+                        && (x, y) != self.coordinates()
+                        && map.get(x, y).is_some_and(|cell| cell.is_passable(config))
+                }) {
+                    let tail = Bot {
+                        x: behind_x,
+                        y: behind_y,
+                        energy: 0.0,
+                        energy_last_tick: 0.0,
+                        body: Some(BodyLink::Head(self.x, self.y)),
+                        trace: InstructionTrace::default(),
+                        ..*self
+                    };
+                    map.set(behind_x, behind_y, tail);
+                    self.body = Some(BodyLink::Tail(behind_x, behind_y));
+                    self.energy -= config.movement_cost;
                 }
             }
 
             Instruction::Photosynthesis => {
-                self.energy += config.photosynthesis_energy;
+                let towards_light = config.towards_light(self.x, self.y);
+                let exposure = match towards_light {
+                    Some(towards_light) if config.directional_photosynthesis => {
+                        self.direction.light_exposure(towards_light)
+                    }
+                    _ => 1.0,
+                };
+                // The cell between the bot and the light casts a shadow.
+                // Light doesn't wrap around the map, so the cells closest to it are never shaded
+                let shade = if config.shading
+                    && towards_light
+                        .and_then(|towards| towards.apply_direction_bounded(self.x, self.y, config))
+                        .is_some_and(|(x, y)| map.get(x, y).is_some_and(|cell| cell.alive))
+                {
+                    0.5
+                } else {
+                    1.0
+                };
+                self.energy += config.photosynthesis_energy * exposure * shade;
+                tally.photosynthesized += 1;
             }
             Instruction::GiveEnergy => {
-                if cell_in_front.alive {
+                if cell_in_front.alive && !facing_own_body {
                     let energy_to_give = self.current_instruction().energy.clamp(0.0, self.energy);
                     cell_in_front.energy += energy_to_give;
                     self.energy -= energy_to_give;
+                    tally.gave_energy += 1;
                 }
             }
-            Instruction::AttackCell => {
+            Instruction::ShareWithNeighbors => self.share_with_neighbors(map, config, tally),
+            Instruction::AttackCell => 'b: {
+                if config.attack_range > 0 {
+                    self.attack_area(map, config, tally, rng);
+                    break 'b;
+                }
+                if facing_own_body || (cell_in_front.alive && cell_in_front.is_invulnerable()) {
+                    break 'b;
+                }
+
+                if cell_in_front.alive && self.is_relative(cell_in_front, config.kin_compare_mode) {
+                    if config.kin_attack_penalty > 0.0 {
+                        self.energy -= config.kin_attack_penalty;
+                        break 'b;
+                    }
+                    if !config.attack_kin {
+                        break 'b;
+                    }
+                }
+
                 if self.energy >= config.attack_required_energy() && cell_in_front.alive {
                     self.energy -= config.attack_required_energy();
 
                     let taken_energy = f32::min(cell_in_front.energy, config.attack_energy);
                     cell_in_front.energy -= taken_energy;
                     self.energy += taken_energy;
+                    tally.attacked += 1;
+
+                    // Predator takes over one of the victim's genes, at the same position.
+                    // Genome length is fixed, so the instruction pointer stays valid
+                    if config.attack_steals_gene {
+                        let index = rng.gen_range(0..self.genome.len());
+                        self.genome[index] = cell_in_front.genome[index];
+                        self.refresh_fingerprint();
+                    }
                 }
             }
             Instruction::RecycleDeadCell => {
                 if cell_in_front.is_dead() {
                     self.energy += cell_in_front.energy;
                     cell_in_front.empty = true;
+                    tally.recycled += 1;
                 }
             }
 
             Instruction::CheckEnergy => {
-                next_instruction = if self.energy > self.current_instruction().energy {
+                let sensed = config.sensed_energy(self.energy, rng);
+                next_instruction = if sensed > self.current_instruction().energy {
                     self.current_instruction().branch
                 } else {
                     self.current_instruction().branch_alt
                 }
             }
 
+            Instruction::SampleEnergyThreshold => {
+                // Branch is always generated in bounds, but guard against hand-edited genomes
+                let target = self.current_instruction().branch as usize;
+                if let Some(gene) = self.genome.get_mut(target) {
+                    gene.energy = config.sensed_energy(self.energy, rng);
+                }
+            }
+
             Instruction::CheckIfDirectedLeft => {
                 next_instruction = if let Direction::Left = self.direction {
                     self.current_instruction().branch
@@ -234,22 +792,42 @@ impl Bot {
                 }
             }
 
-            Instruction::CheckIfFacingRelative => 'b: {
-                if !cell_in_front.alive {
-                    next_instruction = self.current_instruction().branch_alt;
-                    break 'b;
+            Instruction::CheckAtDistance => {
+                let distance = (self.current_instruction().energy as usize)
+                    .clamp(1, config.vision_range.max(1));
+                let mut seen = Some(self.coordinates());
+                for _ in 0..distance {
+                    seen = seen.and_then(|(x, y)| self.direction.apply_direction(x, y, config));
                 }
 
-                let mut similar_genes = 0;
-
-                let theirs = &cell_in_front.genome;
-                for (i, gene) in theirs.iter().enumerate() {
-                    if self.genome[i].instruction == gene.instruction {
-                        similar_genes += 1;
+                // Beyond an edge that doesn't wrap there's a wall, which is neither alive nor empty
+                let option = self.current_instruction().option;
+                let matches = seen.and_then(|(x, y)| map.get(x, y)).is_some_and(|seen| {
+                    if option {
+                        seen.alive
+                    } else {
+                        seen.empty
                     }
+                });
+                next_instruction = if matches {
+                    self.current_instruction().branch
+                } else {
+                    self.current_instruction().branch_alt
                 }
+            }
 
-                next_instruction = if similar_genes == config::GENOME_LENGTH {
+            Instruction::CheckLastFailed => {
+                next_instruction = if self.last_action_failed {
+                    self.current_instruction().branch
+                } else {
+                    self.current_instruction().branch_alt
+                }
+            }
+
+            Instruction::CheckIfFacingRelative => {
+                next_instruction = if cell_in_front.alive
+                    && self.is_relative(cell_in_front, config.kin_compare_mode)
+                {
                     self.current_instruction().branch
                 } else {
                     self.current_instruction().branch_alt
@@ -257,48 +835,744 @@ impl Bot {
             }
 
             Instruction::MakeChild => 'b: {
-                if self.energy < config.reproduction_required_energy && !cell_in_front.empty {
+                let Some((looking_x, looking_y)) = looking else {
+                    // Nothing can be born beyond an edge
+                    next_instruction = self.current_instruction().branch_alt;
+                    break 'b;
+                };
+                // Bodies are never overtaken, since the tail would be left behind
+                let overtakes = config.reproduction_overtakes
+                    && cell_in_front.alive
+                    && cell_in_front.body.is_none()
+                    && !cell_in_front.is_invulnerable()
+                    && cell_in_front.energy < self.energy
+                    && !facing_own_body;
+
+                if self.energy < config.reproduction_required_energy
+                    || !(cell_in_front.is_passable(config) || overtakes)
+                    || self.ticks_since_reproduction < config.reproduction_cooldown
+                    || self.age < config.reproduction_min_age
+                {
                     next_instruction = self.current_instruction().branch_alt;
                     break 'b;
                 }
+                if overtakes {
+                    let mut victim = *cell_in_front;
+                    victim.die(config);
+                    displaced = Some(victim);
+                }
+
+                // Either the parent splits it's energy with the child,
+                // or the child gets fixed energy, and the parent pays a fixed cost
+                let (child_energy, mut reproduction_cost) = match config.child_energy_fraction {
+                    Some(fraction) => (self.energy * fraction, self.energy * fraction),
+                    None => (config.start_energy, config.reproduction_required_energy),
+                };
 
                 let mut child = Bot {
                     x: looking_x,
                     y: looking_y,
                     age: 0,
-                    energy: config.start_energy,
+                    age_offset: Self::random_age_offset(rng, config),
+                    ticks_since_reproduction: 0,
+                    frozen: false,
+                    last_action_failed: false,
+                    body: None,
+                    invulnerable_until: config.newborn_invulnerable_ticks,
+                    direction: match config.newborn_faces {
+                        NewbornFacing::SameAsParent => self.direction,
+                        NewbornFacing::AwayFromParent => self.direction.opposite(),
+                        NewbornFacing::Random => Direction::generate_random_with_rng(rng),
+                    },
+                    energy: child_energy,
+                    energy_last_tick: child_energy,
                     current_instruction: 0,
+                    trace: InstructionTrace::default(),
                     ..*self
                 };
 
-                if rand::thread_rng().gen_bool(config.mutation_percent / 100.0) {
-                    let gene_to_mutate =
-                        rand::thread_rng().gen_range(0..config::GENOME_LENGTH as usize - 1);
-                    child.genome[gene_to_mutate].mutate(config);
+                if let Some(mode) = config.crossover_mode {
+                    // The cell in front is where the child goes, so the mate is any other neighbor.
+                    // An overtaken bot is alive, but it's about to be replaced.
+                    // Tails are skipped, they only carry a copy of their head's genome
+                    let mate = map
+                        .neighbors(self.x, self.y, config.wrap_x, config.wrap_y)
+                        .filter(|&(x, y)| (x, y) != (looking_x, looking_y))
+                        .filter(|&(x, y)| {
+                            map.get(x, y)
+                                .is_some_and(|cell| cell.alive && !cell.is_tail())
+                        })
+                        .choose(rng);
+
+                    if let Some((mate_x, mate_y)) = mate {
+                        let mate = map.get_mut(mate_x, mate_y).unwrap();
+                        child.genome = gene::crossover(&self.genome, &mate.genome, mode, rng);
+                        child.refresh_fingerprint();
+
+                        let mate_share = (reproduction_cost / 2.).clamp(0.0, mate.energy.max(0.0));
+                        mate.energy -= mate_share;
+                        reproduction_cost -= mate_share;
+                    }
+                }
+
+                let genome_mutated = rng.gen_bool(config.mutation_percent / 100.0);
+                if genome_mutated {
+                    let gene_to_mutate = rng.gen_range(0..config::GENOME_LENGTH as usize - 1);
+                    child.genome[gene_to_mutate].mutate_with_rng(rng, config);
+                    child.refresh_fingerprint();
+                }
+                if genome_mutated || config.always_mutate_color {
                     // Mutate child's color to be slightly different from the parent
-                    child.color.mutate(16.0);
+                    child.color.mutate_with_rng(rng, 16.0);
                 }
 
                 map.set(child.x, child.y, child);
-                self.energy -= config.reproduction_required_energy;
+                self.energy -= reproduction_cost;
+                self.ticks_since_reproduction = 0;
+                tally.reproduced += 1;
                 next_instruction = self.current_instruction().branch;
             }
 
             Instruction::Noop => {}
         }
 
+        if matches!(
+            instruction,
+            Instruction::MoveForwards | Instruction::AttackCell | Instruction::MakeChild
+        ) {
+            self.last_action_failed =
+                tally.moved + tally.attacked + tally.reproduced == succeeded_before;
+        }
+
         // If instruction pointer goes beyond the end of genome, wrap around
         if next_instruction >= config::GENOME_LENGTH {
             next_instruction = 0;
         }
         self.current_instruction = next_instruction;
+        displaced
+    }
+}
 
-        self.energy -= config.noop_cost;
-        // Cell can die of age, or if it has less than 0 energy
-        if self.age > config.cell_max_age || self.energy < 0.0 {
-            self.alive = false;
+/// Whether the cells at given coordinates are the head and the tail of the same body
+pub fn is_attached(map: &Map<Bot>, head: (usize, usize), tail: (usize, usize)) -> bool {
+    map.get(head.0, head.1)
+        .is_some_and(|cell| cell.body == Some(BodyLink::Tail(tail.0, tail.1)))
+        && map
+            .get(tail.0, tail.1)
+            .is_some_and(|cell| cell.body == Some(BodyLink::Head(head.0, head.1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gene::Instruction;
+
+    fn test_config(width: usize, height: usize) -> Config {
+        Config {
+            width,
+            height,
+            initial_direction: Some(Direction::Right),
+            ..Default::default()
         }
+    }
 
-        self.age += 1;
+    fn empty_map(config: &Config) -> Map<Bot> {
+        let mut map = Map::new(config.width, config.height);
+        for x in 0..config.width {
+            for y in 0..config.height {
+                map.set(x, y, Bot::new_empty(x, y));
+            }
+        }
+        map
+    }
+
+    // Alive bot which executes `instruction` with given gene energy first
+    fn bot_with(x: usize, y: usize, instruction: Instruction, energy: f32, config: &Config) -> Bot {
+        let mut genome = [Gene::default(); config::GENOME_LENGTH as usize];
+        genome[0].instruction = instruction;
+        genome[0].energy = energy;
+        Bot::new_with_genome(x, y, genome, config)
+    }
+
+    fn place(map: &mut Map<Bot>, bot: Bot) {
+        map.set(bot.x(), bot.y(), bot);
+    }
+
+    // Execute the bot's current instruction, with a seeded generator
+    fn execute(bot: &mut Bot, map: &mut Map<Bot>, config: &Config) -> Option<Bot> {
+        let mut rng = StdRng::seed_from_u64(0);
+        bot.execute_instruction(map, config, &mut ActionTally::default(), &mut rng)
+    }
+
+    #[test]
+    fn share_with_neighbors_splits_energy_equally() {
+        let config = test_config(3, 3);
+        let mut map = empty_map(&config);
+        let mut donor = bot_with(1, 1, Instruction::ShareWithNeighbors, 8.0, &config);
+        donor.energy = 10.0;
+        place(&mut map, donor);
+
+        let neighbors = [(0, 0), (2, 0), (0, 2), (2, 2)];
+        for &(x, y) in neighbors.iter() {
+            let mut neighbor = bot_with(x, y, Instruction::Noop, 0.0, &config);
+            neighbor.energy = 0.0;
+            place(&mut map, neighbor);
+        }
+
+        execute(&mut donor, &mut map, &config);
+
+        assert_eq!(donor.energy, 2.0);
+        for &(x, y) in neighbors.iter() {
+            assert_eq!(map.get(x, y).unwrap().energy, 2.0);
+        }
+    }
+
+    #[test]
+    fn share_with_neighbors_gives_nothing_without_energy() {
+        let config = test_config(3, 3);
+        let mut map = empty_map(&config);
+        let mut donor = bot_with(1, 1, Instruction::ShareWithNeighbors, 8.0, &config);
+        donor.energy = -1.0;
+        place(&mut map, donor);
+        let mut neighbor = bot_with(0, 0, Instruction::Noop, 0.0, &config);
+        neighbor.energy = 0.0;
+        place(&mut map, neighbor);
+
+        execute(&mut donor, &mut map, &config);
+
+        assert_eq!(donor.energy, -1.0);
+        assert_eq!(map.get(0, 0).unwrap().energy, 0.0);
+    }
+
+    #[test]
+    fn looking_past_an_edge_sees_a_wall() {
+        let config = Config {
+            wrap_x: false,
+            ..test_config(3, 3)
+        };
+        let mut map = empty_map(&config);
+        let mut bot = bot_with(2, 1, Instruction::CheckAtDistance, 1.0, &config);
+        bot.genome[0].option = true;
+        bot.genome[0].branch = 1;
+        bot.genome[0].branch_alt = 2;
+        place(&mut map, bot);
+
+        execute(&mut bot, &mut map, &config);
+
+        // The bot's own copy in the map is never mistaken for a neighbor
+        assert_eq!(bot.current_instruction, 2);
+    }
+
+    // Parent at (0, 0) facing a bot with given energy at (1, 0)
+    fn overtake_setup(target_energy: f32) -> (Config, Map<Bot>, Bot) {
+        let config = Config {
+            reproduction_overtakes: true,
+            mutation_percent: 0.0,
+            ..test_config(3, 1)
+        };
+        let mut map = empty_map(&config);
+        let mut parent = bot_with(0, 0, Instruction::MakeChild, 0.0, &config);
+        parent.energy = 100.0;
+        place(&mut map, parent);
+        let mut target = bot_with(1, 0, Instruction::Noop, 0.0, &config);
+        target.energy = target_energy;
+        place(&mut map, target);
+        (config, map, parent)
+    }
+
+    #[test]
+    fn strong_bot_overtakes_weak_neighbor() {
+        let (config, mut map, mut parent) = overtake_setup(1.0);
+        let target_lineage = map.get(1, 0).unwrap().lineage;
+
+        let displaced = execute(&mut parent, &mut map, &config);
+
+        let victim = displaced.expect("the weaker bot should be displaced");
+        assert!(!victim.alive);
+        assert_eq!(victim.lineage, target_lineage);
+        let child = map.get(1, 0).unwrap();
+        assert!(child.alive);
+        assert_eq!(child.lineage, parent.lineage);
+    }
+
+    #[test]
+    fn strong_neighbor_is_not_overtaken() {
+        let (config, mut map, mut parent) = overtake_setup(200.0);
+        let target_lineage = map.get(1, 0).unwrap().lineage;
+
+        let displaced = execute(&mut parent, &mut map, &config);
+
+        assert!(displaced.is_none());
+        assert_eq!(map.get(1, 0).unwrap().lineage, target_lineage);
+        assert_eq!(parent.energy, 100.0);
+    }
+
+    #[test]
+    fn protected_newborn_is_not_overtaken() {
+        let (config, mut map, mut parent) = overtake_setup(1.0);
+        map.get_mut(1, 0).unwrap().invulnerable_until = 10;
+
+        let displaced = execute(&mut parent, &mut map, &config);
+
+        assert!(displaced.is_none());
+        assert!(map.get(1, 0).unwrap().is_invulnerable());
+    }
+
+    // Config with multicell bodies enabled, bot at (1, 1) facing right
+    fn body_setup(instruction: Instruction) -> (Config, Map<Bot>, Bot) {
+        let config = Config {
+            multicell: true,
+            mutation_percent: 0.0,
+            ..test_config(4, 3)
+        };
+        let mut map = empty_map(&config);
+        let mut head = bot_with(1, 1, instruction, 0.0, &config);
+        head.energy = 100.0;
+        place(&mut map, head);
+        (config, map, head)
+    }
+
+    #[test]
+    fn grow_places_tail_behind() {
+        let (config, mut map, mut head) = body_setup(Instruction::Grow);
+
+        execute(&mut head, &mut map, &config);
+
+        assert_eq!(head.tail(), Some((0, 1)));
+        let tail = map.get(0, 1).unwrap();
+        assert!(tail.is_tail());
+        assert_eq!(tail.body, Some(BodyLink::Head(1, 1)));
+    }
+
+    #[test]
+    fn grow_does_nothing_when_disabled() {
+        let (mut config, mut map, mut head) = body_setup(Instruction::Grow);
+        config.multicell = false;
+
+        execute(&mut head, &mut map, &config);
+
+        assert_eq!(head.body, None);
+        assert!(map.get(0, 1).unwrap().empty);
+    }
+
+    #[test]
+    fn own_tail_is_not_a_mate() {
+        let (mut config, mut map, mut head) = body_setup(Instruction::MakeChild);
+        config.crossover_mode = Some(config::CrossoverMode::Uniform);
+        let mut tail = head;
+        tail.set_coordinates(0, 1);
+        tail.energy = 50.0;
+        tail.body = Some(BodyLink::Head(1, 1));
+        head.body = Some(BodyLink::Tail(0, 1));
+        place(&mut map, head);
+        place(&mut map, tail);
+
+        execute(&mut head, &mut map, &config);
+
+        assert!(map.get(2, 1).unwrap().alive);
+        assert_eq!(map.get(0, 1).unwrap().energy, 50.0);
+        assert_eq!(head.energy, 100.0 - config.reproduction_required_energy);
+    }
+
+    #[test]
+    fn moving_towards_the_light_costs_more() {
+        let config = Config {
+            phototaxis_cost: 2.0,
+            ..test_config(3, 10)
+        };
+        // Light comes from the bottom of the map
+        let energy_after_move = |direction| {
+            let mut map = empty_map(&config);
+            let mut bot = bot_with(1, 4, Instruction::MoveForwards, 0.0, &config);
+            bot.direction = direction;
+            bot.energy = 10.0;
+            place(&mut map, bot);
+            execute(&mut bot, &mut map, &config);
+            bot.energy
+        };
+
+        let towards = energy_after_move(Direction::Down);
+        let away = energy_after_move(Direction::Up);
+        assert!((away - towards - 0.2).abs() < 1e-5, "{towards} vs {away}");
+    }
+
+    #[test]
+    fn same_seed_gives_same_random_bot() {
+        let config = Config::default();
+        let random_bot = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut bot = Bot::new_random_with_rng(&mut rng, 1, 1, &config);
+            bot.genome[0].mutate_with_rng(&mut rng, &config);
+            bot.color.mutate_with_rng(&mut rng, 16.0);
+            serde_json::to_string(&bot).unwrap()
+        };
+
+        assert_eq!(random_bot(7), random_bot(7));
+        assert_ne!(random_bot(7), random_bot(8));
+    }
+
+    #[test]
+    fn photosynthesis_follows_the_light_axis() {
+        let config = Config {
+            light_axis: config::LightAxis::X,
+            directional_photosynthesis: true,
+            shading: true,
+            photosynthesis_energy: 4.0,
+            ..test_config(3, 3)
+        };
+        // Energy gained by a bot at (1, 1) facing `direction`, with alive neighbors at `others`
+        let gained = |direction, others: &[(usize, usize)]| {
+            let mut map = empty_map(&config);
+            let mut bot = bot_with(1, 1, Instruction::Photosynthesis, 0.0, &config);
+            bot.direction = direction;
+            bot.energy = 0.0;
+            place(&mut map, bot);
+            for &(x, y) in others {
+                place(&mut map, bot_with(x, y, Instruction::Noop, 0.0, &config));
+            }
+            execute(&mut bot, &mut map, &config);
+            bot.energy
+        };
+
+        // Light comes from the right
+        assert_eq!(gained(Direction::Right, &[]), 4.0);
+        assert_eq!(gained(Direction::Up, &[]), 2.0);
+        assert_eq!(gained(Direction::Left, &[]), 1.0);
+        // Only the neighbor towards the light casts a shadow
+        assert_eq!(gained(Direction::Right, &[(2, 1)]), 2.0);
+        assert_eq!(gained(Direction::Right, &[(0, 1), (1, 2)]), 4.0);
+    }
+
+    #[test]
+    fn newborn_faces_according_to_config() {
+        // Parent at (0, 0) facing right, so the child is born at (1, 0)
+        let child_direction = |newborn_faces, seed| {
+            let config = Config {
+                newborn_faces,
+                mutation_percent: 0.0,
+                ..test_config(3, 1)
+            };
+            let mut map = empty_map(&config);
+            let mut parent = bot_with(0, 0, Instruction::MakeChild, 0.0, &config);
+            parent.energy = 100.0;
+            place(&mut map, parent);
+            let mut rng = StdRng::seed_from_u64(seed);
+            parent.execute_instruction(&mut map, &config, &mut ActionTally::default(), &mut rng);
+            let child = map.get(1, 0).unwrap();
+            assert!(child.alive);
+            child.direction
+        };
+
+        assert_eq!(
+            child_direction(NewbornFacing::SameAsParent, 0),
+            Direction::Right
+        );
+        assert_eq!(
+            child_direction(NewbornFacing::AwayFromParent, 0),
+            Direction::Left
+        );
+        let random: Vec<_> = (0..20)
+            .map(|seed| child_direction(NewbornFacing::Random, seed))
+            .collect();
+        assert!(random.iter().any(|&direction| direction != random[0]));
+    }
+
+    #[test]
+    fn mutation_refreshes_the_fingerprint() {
+        let config = Config {
+            mutation_percent: 100.0,
+            ..test_config(3, 1)
+        };
+        let mut instructions_changed = false;
+        for seed in 0..50 {
+            let mut map = empty_map(&config);
+            let mut parent = bot_with(0, 0, Instruction::MakeChild, 0.0, &config);
+            parent.energy = 100.0;
+            place(&mut map, parent);
+            let mut rng = StdRng::seed_from_u64(seed);
+            parent.execute_instruction(&mut map, &config, &mut ActionTally::default(), &mut rng);
+
+            let child = map.get(1, 0).unwrap();
+            assert_eq!(
+                child.instruction_fingerprint(),
+                child.genome_fingerprint(KinCompareMode::InstructionsOnly)
+            );
+            instructions_changed |=
+                child.instruction_fingerprint() != parent.instruction_fingerprint();
+        }
+        assert!(instructions_changed);
+    }
+
+    #[test]
+    fn stolen_gene_refreshes_the_fingerprint() {
+        // Both the attack in front and the area attack steal genes
+        for attack_range in [0, 1] {
+            let config = Config {
+                attack_steals_gene: true,
+                attack_range,
+                ..test_config(3, 3)
+            };
+            let mut map = empty_map(&config);
+            let mut predator = bot_with(0, 0, Instruction::AttackCell, 0.0, &config);
+            predator.genome = [Gene {
+                instruction: Instruction::AttackCell,
+                ..Default::default()
+            }; config::GENOME_LENGTH as usize];
+            predator.refresh_fingerprint();
+            predator.energy = 100.0;
+            place(&mut map, predator);
+            let mut prey = bot_with(1, 0, Instruction::Photosynthesis, 0.0, &config);
+            prey.genome = [Gene {
+                instruction: Instruction::Photosynthesis,
+                ..Default::default()
+            }; config::GENOME_LENGTH as usize];
+            prey.refresh_fingerprint();
+            prey.energy = 100.0;
+            place(&mut map, prey);
+            let before = predator.instruction_fingerprint();
+
+            execute(&mut predator, &mut map, &config);
+
+            assert_ne!(predator.instruction_fingerprint(), before);
+            assert_eq!(
+                predator.instruction_fingerprint(),
+                predator.genome_fingerprint(KinCompareMode::InstructionsOnly)
+            );
+        }
+    }
+
+    #[test]
+    fn walls_cant_be_entered_or_destroyed() {
+        let config = test_config(3, 3);
+        let actions = [
+            Instruction::MoveForwards,
+            Instruction::MakeChild,
+            Instruction::AttackCell,
+            Instruction::RecycleDeadCell,
+        ];
+        for instruction in actions {
+            let mut map = empty_map(&config);
+            let mut bot = bot_with(0, 1, instruction, 0.0, &config);
+            bot.energy = 100.0;
+            place(&mut map, bot);
+            map.set(1, 1, Bot::new_wall(1, 1));
+
+            let mut tally = ActionTally::default();
+            let mut rng = StdRng::seed_from_u64(0);
+            bot.execute_instruction(&mut map, &config, &mut tally, &mut rng);
+
+            assert_eq!(bot.coordinates(), (0, 1), "{instruction:?}");
+            assert_eq!(bot.energy, 100.0, "{instruction:?}");
+            assert_eq!(tally, ActionTally::default(), "{instruction:?}");
+            let wall = map.get(1, 1).unwrap();
+            assert!(wall.wall && !wall.alive && !wall.empty, "{instruction:?}");
+        }
+    }
+
+    #[test]
+    fn bot_moves_and_reproduces_where_there_is_no_wall() {
+        let config = test_config(3, 3);
+        for instruction in [Instruction::MoveForwards, Instruction::MakeChild] {
+            let mut map = empty_map(&config);
+            let mut bot = bot_with(0, 1, instruction, 0.0, &config);
+            bot.energy = 100.0;
+            place(&mut map, bot);
+
+            let mut tally = ActionTally::default();
+            let mut rng = StdRng::seed_from_u64(0);
+            bot.execute_instruction(&mut map, &config, &mut tally, &mut rng);
+
+            assert_eq!(tally.moved + tally.reproduced, 1, "{instruction:?}");
+        }
+    }
+
+    #[test]
+    fn sample_energy_threshold_writes_energy_into_target_gene() {
+        let config = test_config(3, 3);
+        let mut map = empty_map(&config);
+        let mut bot = bot_with(1, 1, Instruction::SampleEnergyThreshold, 0.0, &config);
+        bot.genome[0].branch = 5;
+        bot.energy = 42.5;
+        place(&mut map, bot);
+
+        execute(&mut bot, &mut map, &config);
+
+        assert_eq!(bot.genome[5].energy, 42.5);
+        // Only the target gene is changed
+        assert_eq!(bot.genome[0].energy, 0.0);
+    }
+
+    #[test]
+    fn kin_is_attacked_only_if_allowed() {
+        for attack_kin in [false, true] {
+            let config = Config {
+                attack_kin,
+                ..test_config(3, 3)
+            };
+            let mut map = empty_map(&config);
+            let mut attacker = bot_with(0, 1, Instruction::AttackCell, 0.0, &config);
+            attacker.energy = 100.0;
+            place(&mut map, attacker);
+            let mut twin = attacker;
+            twin.set_coordinates(1, 1);
+            place(&mut map, twin);
+
+            execute(&mut attacker, &mut map, &config);
+
+            let damaged = map.get(1, 1).unwrap().energy < 100.0;
+            assert_eq!(damaged, attack_kin);
+            assert_eq!(attacker.energy > 100.0, attack_kin);
+        }
+    }
+
+    #[test]
+    fn spinning_costs_turn_and_noop_cost() {
+        let config = Config {
+            turn_cost: 0.25,
+            movement_cost: 4.0,
+            noop_cost: 0.5,
+            ambient_energy_per_tick: 0.0,
+            min_energy_upkeep: 0.0,
+            ..test_config(3, 3)
+        };
+        let mut map = empty_map(&config);
+        let mut bot = bot_with(1, 1, Instruction::TurnLeft, 0.0, &config);
+        bot.genome = [bot.genome[0]; config::GENOME_LENGTH as usize];
+        bot.energy = 10.0;
+        place(&mut map, bot);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for tick in 1..=4 {
+            bot.update(&mut map, &config, &mut ActionTally::default(), &mut rng);
+            assert_eq!(bot.energy, 10.0 - 0.75 * tick as f32);
+        }
+        assert_eq!(bot.coordinates(), (1, 1));
+    }
+
+    #[test]
+    fn bot_crosses_only_wrapping_edges() {
+        // Start next to an edge, facing across it, and where the bot ends up after moving
+        let moves = [
+            (Direction::Right, (2, 1), (0, 1)),
+            (Direction::Left, (0, 1), (2, 1)),
+            (Direction::Down, (1, 2), (1, 0)),
+            (Direction::Up, (1, 0), (1, 2)),
+        ];
+        for (wrap_x, wrap_y) in [(true, true), (true, false), (false, true), (false, false)] {
+            let config = Config {
+                wrap_x,
+                wrap_y,
+                ..test_config(3, 3)
+            };
+            for (direction, (x, y), across) in moves {
+                let mut map = empty_map(&config);
+                let mut bot = bot_with(x, y, Instruction::MoveForwards, 0.0, &config);
+                bot.direction = direction;
+                place(&mut map, bot);
+
+                execute(&mut bot, &mut map, &config);
+
+                let wraps = if matches!(direction, Direction::Left | Direction::Right) {
+                    wrap_x
+                } else {
+                    wrap_y
+                };
+                let expected = if wraps { across } else { (x, y) };
+                assert_eq!(
+                    bot.coordinates(),
+                    expected,
+                    "{direction:?} {wrap_x} {wrap_y}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn facing_the_light_yields_more() {
+        // Energy a lone bot gains from photosynthesis facing `direction`
+        let gained = |directional_photosynthesis, direction| {
+            let config = Config {
+                directional_photosynthesis,
+                photosynthesis_energy: 4.0,
+                ..test_config(3, 3)
+            };
+            let mut map = empty_map(&config);
+            let mut bot = bot_with(1, 1, Instruction::Photosynthesis, 0.0, &config);
+            bot.direction = direction;
+            bot.energy = 0.0;
+            place(&mut map, bot);
+            execute(&mut bot, &mut map, &config);
+            bot.energy
+        };
+
+        // Along the default Y axis, light gets brighter downwards
+        assert_eq!(gained(true, Direction::Down), 4.0);
+        assert_eq!(gained(true, Direction::Up), 1.0);
+        assert_eq!(gained(false, Direction::Down), 4.0);
+        assert_eq!(gained(false, Direction::Up), 4.0);
+    }
+
+    #[test]
+    fn energy_split_conserves_energy() {
+        let config = Config {
+            child_energy_fraction: Some(0.25),
+            ..test_config(3, 1)
+        };
+        let mut map = empty_map(&config);
+        let mut parent = bot_with(0, 0, Instruction::MakeChild, 0.0, &config);
+        parent.energy = 100.0;
+        place(&mut map, parent);
+
+        execute(&mut parent, &mut map, &config);
+
+        let child = map.get(1, 0).unwrap();
+        assert!(child.alive);
+        assert_eq!(child.energy, 25.0);
+        assert_eq!(parent.energy + child.energy, 100.0);
+    }
+
+    #[test]
+    fn checks_resolve_within_the_budget() {
+        let config = Config {
+            instructions_per_tick: 4,
+            ..test_config(3, 3)
+        };
+        // The bot faces right, so both checks jump to their alternative branch
+        let mut genome = [Gene::default(); config::GENOME_LENGTH as usize];
+        genome[0].instruction = Instruction::CheckIfDirectedUp;
+        genome[0].branch_alt = 5;
+        genome[5].instruction = Instruction::CheckIfDirectedLeft;
+        genome[5].branch_alt = 9;
+        genome[9].instruction = Instruction::Photosynthesis;
+        let mut map = empty_map(&config);
+        let mut bot = Bot::new_with_genome(1, 1, genome, &config);
+        place(&mut map, bot);
+
+        let mut tally = ActionTally::default();
+        let mut rng = StdRng::seed_from_u64(0);
+        bot.update(&mut map, &config, &mut tally, &mut rng);
+
+        assert_eq!(tally.photosynthesized, 1);
+        assert_eq!(bot.current_instruction, 10);
+        assert_eq!(bot.trace.iter().collect::<Vec<_>>(), vec![0, 5, 9]);
+    }
+
+    #[test]
+    fn endless_checks_stop_at_the_budget() {
+        let config = Config {
+            instructions_per_tick: 4,
+            ..test_config(3, 3)
+        };
+        // A check that always jumps back to itself
+        let mut bot = bot_with(1, 1, Instruction::CheckIfDirectedUp, 0.0, &config);
+        bot.genome[0].branch_alt = 0;
+        let mut map = empty_map(&config);
+        place(&mut map, bot);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        bot.update(&mut map, &config, &mut ActionTally::default(), &mut rng);
+
+        assert_eq!(bot.trace.iter().collect::<Vec<_>>(), vec![0; 4]);
+        assert!(bot.alive);
     }
 }
@@ -50,10 +50,30 @@ impl Color {
         self.2
     }
 
+    /// Color picked by a hash, so that equal hashes always give the same color
+    pub fn from_hash(hash: u64) -> Self {
+        let [r, g, b, ..] = hash.to_le_bytes();
+        Color(r, g, b)
+    }
+
+    /// Linear interpolation between two colors, `t` of 0 gives `self`, 1 gives `other`
+    pub fn lerp(self, other: Color, t: f64) -> Self {
+        let t = t.clamp(0., 1.);
+        let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t) as u8;
+
+        Color(
+            mix(self.0, other.0),
+            mix(self.1, other.1),
+            mix(self.2, other.2),
+        )
+    }
+
     /// Change a random color component by a random number in range `(-amount..=amount)`
     pub fn mutate(&mut self, amount: f64) {
-        let mut rng = thread_rng();
-
+        self.mutate_with_rng(&mut thread_rng(), amount)
+    }
+    /// Same as [`Color::mutate`], but takes the randomness from given generator
+    pub fn mutate_with_rng(&mut self, rng: &mut impl Rng, amount: f64) {
         // Convert the color components to f64 and mutate them,
         // this is to not overflow the original u8 type
         let mut r = self.r() as f64;
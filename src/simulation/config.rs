@@ -1,7 +1,164 @@
+use std::fmt::Debug;
+use std::ops::RangeInclusive;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::direction::Direction;
+use super::gene::InstructionSet;
+use super::stats::SpeciesDefinition;
+
 // This is used in array length, so it must be a constant
 pub const GENOME_LENGTH: u8 = 32;
 
-#[derive(Clone, Copy, PartialEq)]
+// Valid ranges of the parameters. Checked by `Config::validate()`,
+// and used to clamp the values in the settings window
+pub const MUTATION_PERCENT_RANGE: RangeInclusive<f64> = 0.0..=100.0;
+// Start energy, photosynthesis and attack energy, and all the costs
+pub const ENERGY_RANGE: RangeInclusive<f32> = 0.0..=f32::MAX;
+// Genes pick their energy from `0..reproduction_required_energy * 2`, so it can't be 0
+pub const REPRODUCTION_ENERGY_RANGE: RangeInclusive<f32> = 0.1..=f32::MAX;
+pub const FRACTION_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+pub const MAX_AGE_RANGE: RangeInclusive<u32> = 1..=u32::MAX;
+// Age offset is stored as i32
+pub const MAX_AGE_JITTER_RANGE: RangeInclusive<u32> = 0..=i32::MAX as u32;
+// A bot can run through it's whole genome in one tick at most
+pub const INSTRUCTIONS_PER_TICK_RANGE: RangeInclusive<u32> = 1..=GENOME_LENGTH as u32;
+pub const LIGHT_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+pub const DAY_NIGHT_PERIOD_RANGE: RangeInclusive<usize> = 1..=usize::MAX;
+pub const HISTOGRAM_BUCKETS_RANGE: RangeInclusive<usize> = 1..=100;
+pub const HISTOGRAM_MAX_ENERGY_RANGE: RangeInclusive<f32> = 0.1..=f32::MAX;
+pub const VISION_RANGE_RANGE: RangeInclusive<usize> = 1..=8;
+// Number of cells hit grows quadratically with the range, so keep it small
+pub const ATTACK_RANGE_RANGE: RangeInclusive<usize> = 0..=3;
+pub const INITIAL_GENOME_POOL_RANGE: RangeInclusive<usize> = 1..=usize::MAX;
+pub const TIMELAPSE_INTERVAL_RANGE: RangeInclusive<usize> = 1..=usize::MAX;
+pub const ASCII_INTERVAL_RANGE: RangeInclusive<usize> = 1..=usize::MAX;
+pub const MAX_TICKS_PER_FRAME_RANGE: RangeInclusive<usize> = 1..=usize::MAX;
+pub const MUTATION_SEASON_PERIOD_RANGE: RangeInclusive<usize> = 1..=usize::MAX;
+// A cell has at most 8 neighbors
+pub const OVERCROWD_RANGE: RangeInclusive<usize> = 0..=8;
+// Nudging a branch by more than half the genome is the same as nudging it the other way
+pub const BRANCH_JITTER_RANGE: RangeInclusive<u8> = 1..=GENOME_LENGTH / 2;
+pub const SPECIES_RADIUS_RANGE: RangeInclusive<u8> = 0..=GENOME_LENGTH;
+pub const STAGNATION_WINDOW_RANGE: RangeInclusive<usize> = 1..=usize::MAX;
+
+/// What happens to a cell when it dies
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DeathMode {
+    /// Cell becomes a dead cell, keeping it's energy for others to recycle
+    LeaveCorpse,
+    /// Cell disappears immediately, and it's energy is lost
+    Vanish,
+}
+
+/// Which parts of the genes are compared when checking whether two bots are relatives
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum KinCompareMode {
+    /// Only the sequence of instructions
+    InstructionsOnly,
+    /// All the fields of every gene: instruction, option, energy and both branches
+    FullGene,
+}
+
+/// Where the light comes from, see [`Config::light_axis`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LightAxis {
+    /// Brightest at the bottom of the map, dark at the top
+    Y,
+    /// Brightest at the right edge of the map, dark at the left one
+    X,
+    /// Brightest in the center of the map, dark in the corners
+    Radial,
+}
+
+/// Which way a newborn bot faces, see [`Config::newborn_faces`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum NewbornFacing {
+    /// Same direction as the parent, so away from it, as the child is born in front of the parent
+    SameAsParent,
+    /// Opposite to the parent's direction. The child is born in front of the parent,
+    /// so despite the name, it faces back towards the parent
+    AwayFromParent,
+    /// Random direction
+    Random,
+}
+
+/// How the genomes of two parents are combined, see [`Config::crossover_mode`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CrossoverMode {
+    /// Genes before a random point come from the first parent, the rest from the second one
+    SinglePoint,
+    /// Every gene comes from a randomly picked parent
+    Uniform,
+}
+
+/// How a mutation changes the branch targets of a gene
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BranchMutationMode {
+    /// Branch jumps to a random gene
+    Uniform,
+    /// Branch moves by up to this many genes in either direction, wrapping around the genome
+    LocalJitter(u8),
+}
+
+/// How [`Simulation::generate_map`](super::Simulation::generate_map) places the initial bots
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SeedingPattern {
+    /// 20% chance of a bot on every cell
+    UniformRandom,
+    /// Bots are concentrated in the middle of the map, getting sparser towards the edges
+    CenterClump,
+    /// Bots are spawned only in the four corners of the map
+    Corners,
+    /// Every other cell gets a bot
+    Checkerboard,
+    /// A single bot in the center of the map
+    SingleBot,
+}
+
+impl SeedingPattern {
+    /// Chance (`0.0..=1.0`) that a bot will be spawned at given coordinates
+    pub fn spawn_chance(&self, x: usize, y: usize, width: usize, height: usize) -> f64 {
+        match self {
+            Self::UniformRandom => 1.0 / 5.0,
+            Self::CenterClump => {
+                // Normalized distance from the center, 0.0 in the center and 1.0 at the edges
+                let dx = (x as f64 - width as f64 / 2.0) / (width as f64 / 2.0);
+                let dy = (y as f64 - height as f64 / 2.0) / (height as f64 / 2.0);
+                let distance = (dx * dx + dy * dy).sqrt();
+
+                (0.5 * (1.0 - distance)).clamp(0.0, 1.0)
+            }
+            Self::Corners => {
+                let near_x_edge = x < width / 4 || x >= width - width / 4;
+                let near_y_edge = y < height / 4 || y >= height - height / 4;
+
+                if near_x_edge && near_y_edge {
+                    1.0 / 5.0
+                } else {
+                    0.0
+                }
+            }
+            Self::Checkerboard => {
+                if x % 2 == y % 2 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Self::SingleBot => {
+                if x == width / 2 && y == height / 2 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Config {
     // Width and height of the simulation field
     pub width: usize,
@@ -10,56 +167,596 @@ pub struct Config {
     // Cell width and height in pixels
     pub cell_size: usize,
 
+    // Whether the map wraps around horizontally and vertically.
+    // Both enabled make the map a torus, only one of them makes it a cylinder
+    pub wrap_x: bool,
+    pub wrap_y: bool,
+
+    // Store only occupied cells. Saves memory on huge, mostly empty maps.
+    // Takes effect when the map is generated
+    pub sparse_map: bool,
+
     // % chance that the child will have 1 gene mutated
     pub mutation_percent: f64,
+    // Length of the mutation season in iterations. Mutation chance rises from `mutation_percent`
+    // to `mutation_peak_percent` and back during each season. None means constant mutation chance
+    pub mutation_season_period: Option<usize>,
+    pub mutation_peak_percent: f64,
+    // Mutate the child's color on every birth, not only when it's genome mutates.
+    // Makes the color drift with the number of generations
+    pub always_mutate_color: bool,
+    // Show bots in the Normal rendering mode with a color derived from their instructions,
+    // instead of their own color. Bots with identical instructions look the same
+    pub color_from_genome: bool,
+    // How mutations change the branch targets
+    pub branch_mutation_mode: BranchMutationMode,
+    // Instructions that can appear in random and mutated genes.
+    // Disabled instructions that are already in a genome act as Noop
+    pub enabled_instructions: InstructionSet,
 
     // Amount of energy the cell spawns with
     pub start_energy: f32,
+    // If set, bots generated with the map get random energy from this range instead
+    pub start_energy_range: Option<(f32, f32)>,
 
     // Energy required for cell to reproduce
     pub reproduction_required_energy: f32,
+    // If set, the parent gives this fraction of it's energy to the child,
+    // instead of the child getting `start_energy`, and the parent paying `reproduction_required_energy`
+    pub child_energy_fraction: Option<f32>,
+    // If set, a reproducing cell with an alive neighbor mates with it. The child's genome
+    // is a crossover of both parents, and the mate pays half of the reproduction cost.
+    // Cells without a neighbor still reproduce alone
+    pub crossover_mode: Option<CrossoverMode>,
+    // Which way children face when they are born
+    pub newborn_faces: NewbornFacing,
+    // Ticks a cell has to wait after being born or reproducing, before it can reproduce again
+    pub reproduction_cooldown: u32,
+    // Age a cell has to reach before it can reproduce at all
+    pub reproduction_min_age: u32,
+    // Number of ticks after birth during which attacks on a child do nothing
+    pub newborn_invulnerable_ticks: u32,
+    // Whether bots can grow a tail with the Grow instruction, making a body of two cells
+    pub multicell: bool,
+    // Whether a child can be born into a cell of an alive bot with strictly less energy
+    // than the parent, killing that bot
+    pub reproduction_overtakes: bool,
 
     // Max age the cell can live
     pub cell_max_age: u32,
+    // Each cell's max age is randomly shifted by up to this many iterations,
+    // so that cells born together don't die together
+    pub max_age_jitter: u32,
+
+    // Maximum number of instructions a cell executes per tick.
+    // Cell stops early after an action (moving, attacking, photosynthesis, ...)
+    pub instructions_per_tick: u32,
+
+    // Whether dead cells stay on the map as corpses
+    pub death_mode: DeathMode,
+    // Whether a dying bot's energy is split between it's alive neighbors,
+    // instead of staying in the corpse
+    pub death_energy_to_neighbors: bool,
+    // Whether corpses block movement and reproduction until they are recycled.
+    // Otherwise bots can move or reproduce into them, destroying the corpse and it's energy
+    pub corpses_block: bool,
+
+    // Cells within this many cells of an edge die. Only edges of axes that don't wrap count
+    pub edge_kill_margin: usize,
+
+    // Cells with more alive neighbors than this die of overcrowding. None disables it
+    pub overcrowd_death: Option<usize>,
 
     // Amount of energy the photosynthesis gives
     pub photosynthesis_energy: f32,
+    // If set, every cell has a limited resource that photosynthesis draws from,
+    // regrowing by `resource_regrowth` each tick up to this cap. None means unlimited
+    pub resource_cap: Option<f32>,
+    pub resource_regrowth: f32,
+
+    // Energy every alive cell gets each tick, regardless of what it does
+    pub ambient_energy_per_tick: f32,
+
+    // Direction of the light gradient. Directional photosynthesis and shading follow it
+    pub light_axis: LightAxis,
+    // Whether photosynthesis gives less energy to bots not facing the light
+    pub directional_photosynthesis: bool,
+
+    // Whether alive cells shade the cell behind them (away from the light),
+    // halving it's photosynthesis
+    pub shading: bool,
+
+    // Length of the day-night cycle in iterations. None means constant light
+    pub day_night_period: Option<usize>,
+    // Light multiplier at midnight and at noon
+    pub night_light: f32,
+    pub day_light: f32,
 
     // Amount energy given from attacking other cell
     pub attack_energy: f32,
 
+    // 0 attacks only the cell in front. Otherwise, all alive cells within this distance
+    // are attacked at once, and the attack energy is split between them
+    pub attack_range: usize,
+
+    // How far the CheckAtDistance instruction can look
+    pub vision_range: usize,
+
+    // Whether cells can attack their relatives
+    pub attack_kin: bool,
+    // Energy a cell loses for attacking it's relative. When above 0, attacks on relatives
+    // only cost the attacker, and take nothing from the victim, even if `attack_kin` is set
+    pub kin_attack_penalty: f32,
+    // Whether a successful attack also copies a random gene from the victim to the attacker
+    pub attack_steals_gene: bool,
+    // How cells recognize their relatives
+    pub kin_compare_mode: KinCompareMode,
+
     pub movement_cost: f32,
+    // Extra energy a move costs for climbing the light gradient, see `light_gradient`.
+    // Charged in proportion to the climb, so this is the cost of the whole gradient
+    pub phototaxis_cost: f32,
+
+    // Cost of turning left/right
+    pub turn_cost: f32,
 
     pub noop_cost: f32,
+    // Cost of every Check* instruction, on top of the noop cost paid each tick
+    pub sense_cost: f32,
+    // Largest error a cell makes when reading energy, models imperfect perception.
+    // Readings are off by a uniformly random amount within this bound, see `sensed_energy`
+    pub sensing_noise: f32,
+    // Baseline metabolism, paid every tick regardless of what the cell does.
+    // Unlike `noop_cost`, it's meant to be tuned independently of the instruction costs
+    pub min_energy_upkeep: f32,
+
+    // Number of buckets in the energy histogram, and energy of the last bucket.
+    // Cells with more energy are counted in the last bucket too
+    pub energy_histogram_buckets: usize,
+    pub energy_histogram_max: f32,
+
+    // Save a frame of the map as PNG every this many iterations, for making time-lapse videos.
+    // The frames are written to the runner's time-lapse directory. Needs the `image` feature
+    pub timelapse_interval: Option<usize>,
+    // Print the map as ASCII art to the standard output every this many iterations.
+    // Useful for watching a run from a terminal
+    pub ascii_interval: Option<usize>,
+    // The simulation thread waits after this many ticks, until the UI takes the next frame.
+    // Unlike a TPS limit, it only bounds how far the simulation gets ahead of the rendering
+    pub max_ticks_per_frame: Option<usize>,
+
+    // How bots are placed when the map is generated
+    pub seeding_pattern: SeedingPattern,
+    // Direction new bots face when they are spawned. None means random.
+    // Children face according to `newborn_faces` instead
+    pub initial_direction: Option<Direction>,
+    // If set, bots placed when the map is generated pick their genome (and color)
+    // from this many random genomes, instead of getting a unique one each
+    pub initial_genome_pool: Option<usize>,
+
+    // Which bots are counted as the same species in the statistics
+    pub species_definition: SpeciesDefinition,
+    // If set, the population is flagged as stagnant once the share of the dominant species
+    // and the share of each instruction have stayed within `stagnation_threshold`
+    // for this many ticks
+    pub stagnation_window: Option<usize>,
+    pub stagnation_threshold: f32,
 }
 
 impl Default for Config {
     fn default() -> Self {
+        let movement_cost = 1.0;
+
         Config {
             width: 160,
             height: 90,
             cell_size: 8,
+            wrap_x: true,
+            wrap_y: true,
+            sparse_map: false,
             mutation_percent: 25.0,
+            mutation_season_period: None,
+            mutation_peak_percent: 75.0,
+            always_mutate_color: false,
+            color_from_genome: false,
+            branch_mutation_mode: BranchMutationMode::Uniform,
+            enabled_instructions: InstructionSet::ALL,
             start_energy: 5.0,
+            start_energy_range: None,
             reproduction_required_energy: 16.0,
+            child_energy_fraction: None,
+            crossover_mode: None,
+            newborn_faces: NewbornFacing::SameAsParent,
+            reproduction_cooldown: 0,
+            reproduction_min_age: 0,
+            newborn_invulnerable_ticks: 0,
+            multicell: false,
+            reproduction_overtakes: false,
             cell_max_age: 2048,
+            max_age_jitter: 0,
+            instructions_per_tick: 1,
+            death_mode: DeathMode::LeaveCorpse,
+            death_energy_to_neighbors: false,
+            corpses_block: true,
+            edge_kill_margin: 0,
+            overcrowd_death: None,
             photosynthesis_energy: 1.0,
+            resource_cap: None,
+            resource_regrowth: 0.1,
+            ambient_energy_per_tick: 0.0,
+            light_axis: LightAxis::Y,
+            directional_photosynthesis: false,
+            shading: false,
+            day_night_period: None,
+            night_light: 0.1,
+            day_light: 1.0,
             attack_energy: 5.0,
-            movement_cost: 1.0,
+            attack_range: 0,
+            vision_range: 2,
+            attack_kin: true,
+            kin_attack_penalty: 0.0,
+            attack_steals_gene: false,
+            kin_compare_mode: KinCompareMode::InstructionsOnly,
+            movement_cost,
+            phototaxis_cost: 0.0,
+            // Turning is half as expensive as moving by default
+            turn_cost: movement_cost / 2.,
             noop_cost: 0.1,
+            sense_cost: 0.0,
+            sensing_noise: 0.0,
+            min_energy_upkeep: 0.0,
+            energy_histogram_buckets: 20,
+            energy_histogram_max: 100.0,
+            timelapse_interval: None,
+            ascii_interval: None,
+            max_ticks_per_frame: None,
+            seeding_pattern: SeedingPattern::UniformRandom,
+            initial_direction: None,
+            initial_genome_pool: None,
+            species_definition: SpeciesDefinition::IdenticalInstructions,
+            stagnation_window: None,
+            stagnation_threshold: 0.01,
         }
     }
 }
 
 impl Config {
-    /// Cost of turning left/right
-    /// Turn cost is always 1/2 of movement cost
-    pub fn turn_cost(&self) -> f32 {
-        self.movement_cost / 2.
+    /// Check that all the parameters are in their valid ranges
+    pub fn validate(&self) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 || self.cell_size == 0 {
+            return Err("map and cell sizes must not be 0".to_string());
+        }
+
+        check_range(
+            "mutation_percent",
+            self.mutation_percent,
+            MUTATION_PERCENT_RANGE,
+        )?;
+        if let Some(period) = self.mutation_season_period {
+            check_range(
+                "mutation_season_period",
+                period,
+                MUTATION_SEASON_PERIOD_RANGE,
+            )?;
+        }
+        check_range(
+            "mutation_peak_percent",
+            self.mutation_peak_percent,
+            MUTATION_PERCENT_RANGE,
+        )?;
+        if let BranchMutationMode::LocalJitter(amount) = self.branch_mutation_mode {
+            check_range("branch_mutation_mode jitter", amount, BRANCH_JITTER_RANGE)?;
+        }
+        check_range("start_energy", self.start_energy, ENERGY_RANGE)?;
+        if let Some((min, max)) = self.start_energy_range {
+            check_range("start_energy_range minimum", min, ENERGY_RANGE)?;
+            check_range("start_energy_range maximum", max, min..=*ENERGY_RANGE.end())?;
+        }
+        check_range(
+            "reproduction_required_energy",
+            self.reproduction_required_energy,
+            REPRODUCTION_ENERGY_RANGE,
+        )?;
+        if let Some(fraction) = self.child_energy_fraction {
+            check_range("child_energy_fraction", fraction, FRACTION_RANGE)?;
+        }
+        check_range("cell_max_age", self.cell_max_age, MAX_AGE_RANGE)?;
+        check_range("max_age_jitter", self.max_age_jitter, MAX_AGE_JITTER_RANGE)?;
+        check_range(
+            "instructions_per_tick",
+            self.instructions_per_tick,
+            INSTRUCTIONS_PER_TICK_RANGE,
+        )?;
+        if let Some(max_neighbors) = self.overcrowd_death {
+            check_range("overcrowd_death", max_neighbors, OVERCROWD_RANGE)?;
+        }
+        check_range(
+            "photosynthesis_energy",
+            self.photosynthesis_energy,
+            ENERGY_RANGE,
+        )?;
+        if let Some(cap) = self.resource_cap {
+            check_range("resource_cap", cap, ENERGY_RANGE)?;
+        }
+        check_range("resource_regrowth", self.resource_regrowth, ENERGY_RANGE)?;
+        if let Some(period) = self.day_night_period {
+            check_range("day_night_period", period, DAY_NIGHT_PERIOD_RANGE)?;
+        }
+        check_range("night_light", self.night_light, LIGHT_RANGE)?;
+        check_range("day_light", self.day_light, LIGHT_RANGE)?;
+        check_range(
+            "ambient_energy_per_tick",
+            self.ambient_energy_per_tick,
+            ENERGY_RANGE,
+        )?;
+        check_range("attack_energy", self.attack_energy, ENERGY_RANGE)?;
+        check_range("attack_range", self.attack_range, ATTACK_RANGE_RANGE)?;
+        check_range("kin_attack_penalty", self.kin_attack_penalty, ENERGY_RANGE)?;
+        check_range("vision_range", self.vision_range, VISION_RANGE_RANGE)?;
+        check_range("movement_cost", self.movement_cost, ENERGY_RANGE)?;
+        check_range("phototaxis_cost", self.phototaxis_cost, ENERGY_RANGE)?;
+        check_range("turn_cost", self.turn_cost, ENERGY_RANGE)?;
+        check_range("noop_cost", self.noop_cost, ENERGY_RANGE)?;
+        check_range("sense_cost", self.sense_cost, ENERGY_RANGE)?;
+        check_range("sensing_noise", self.sensing_noise, ENERGY_RANGE)?;
+        check_range("min_energy_upkeep", self.min_energy_upkeep, ENERGY_RANGE)?;
+        check_range(
+            "energy_histogram_buckets",
+            self.energy_histogram_buckets,
+            HISTOGRAM_BUCKETS_RANGE,
+        )?;
+        check_range(
+            "energy_histogram_max",
+            self.energy_histogram_max,
+            HISTOGRAM_MAX_ENERGY_RANGE,
+        )?;
+        if let Some(pool) = self.initial_genome_pool {
+            check_range("initial_genome_pool", pool, INITIAL_GENOME_POOL_RANGE)?;
+        }
+        if let Some(interval) = self.timelapse_interval {
+            check_range("timelapse_interval", interval, TIMELAPSE_INTERVAL_RANGE)?;
+        }
+        if let Some(interval) = self.ascii_interval {
+            check_range("ascii_interval", interval, ASCII_INTERVAL_RANGE)?;
+        }
+        if let Some(ticks) = self.max_ticks_per_frame {
+            check_range("max_ticks_per_frame", ticks, MAX_TICKS_PER_FRAME_RANGE)?;
+        }
+        if let SpeciesDefinition::SimilarityRadius(radius) = self.species_definition {
+            check_range("species radius", radius, SPECIES_RADIUS_RANGE)?;
+        }
+        if let Some(window) = self.stagnation_window {
+            check_range("stagnation_window", window, STAGNATION_WINDOW_RANGE)?;
+        }
+        check_range(
+            "stagnation_threshold",
+            self.stagnation_threshold,
+            FRACTION_RANGE,
+        )?;
+
+        Ok(())
+    }
+
+    /// Fraction of `photosynthesis_energy` a cell at given coordinates gets on given iteration.
+    /// Light gets brighter along the `light_axis`,
+    /// and follows the day-night cycle, if it is enabled
+    pub fn light_multiplier(&self, x: usize, y: usize, iterations: usize) -> f32 {
+        let daylight = match self.day_night_period {
+            Some(period) if period > 0 => {
+                let phase = (iterations % period) as f32 / period as f32;
+                // 0.0 at midnight, 1.0 at noon
+                let brightness = (1.0 - (phase * std::f32::consts::TAU).cos()) / 2.0;
+                self.night_light + (self.day_light - self.night_light) * brightness
+            }
+            _ => 1.0,
+        };
+
+        self.light_gradient(x, y) * daylight
+    }
+
+    /// Brightness of a cell along the `light_axis`, from 0 to 1, ignoring the day-night cycle
+    pub fn light_gradient(&self, x: usize, y: usize) -> f32 {
+        match self.light_axis {
+            LightAxis::Y => y as f32 / self.height as f32,
+            LightAxis::X => x as f32 / self.width as f32,
+            LightAxis::Radial => {
+                // Distance from the center of the cell to the center of the map
+                let dx = x as f32 + 0.5 - self.width as f32 / 2.0;
+                let dy = y as f32 + 0.5 - self.height as f32 / 2.0;
+                let corner = (self.width as f32).hypot(self.height as f32) / 2.0;
+                1.0 - dx.hypot(dy) / corner
+            }
+        }
+    }
+
+    /// Direction a cell at given coordinates has to face to look towards the light,
+    /// see `light_axis`. None in the very center of radial light, which is lit from all sides
+    pub fn towards_light(&self, x: usize, y: usize) -> Option<Direction> {
+        match self.light_axis {
+            LightAxis::Y => Some(Direction::Down),
+            LightAxis::X => Some(Direction::Right),
+            LightAxis::Radial => {
+                let dx = self.width as f32 / 2.0 - (x as f32 + 0.5);
+                let dy = self.height as f32 / 2.0 - (y as f32 + 0.5);
+                if dx == 0.0 && dy == 0.0 {
+                    None
+                } else if dx.abs() >= dy.abs() {
+                    Some(if dx > 0.0 {
+                        Direction::Right
+                    } else {
+                        Direction::Left
+                    })
+                } else {
+                    Some(if dy > 0.0 {
+                        Direction::Down
+                    } else {
+                        Direction::Up
+                    })
+                }
+            }
+        }
+    }
+
+    /// Extra cost of moving between two cells, see `phototaxis_cost`.
+    /// Moving towards the dark is free
+    pub fn phototaxis_tax(&self, from: (usize, usize), to: (usize, usize)) -> f32 {
+        let climb = self.light_gradient(to.0, to.1) - self.light_gradient(from.0, from.1);
+        climb.max(0.0) * self.phototaxis_cost
+    }
+
+    /// Energy value as a cell perceives it, off by at most `sensing_noise`.
+    /// Exact when there is no noise
+    pub fn sensed_energy(&self, energy: f32, rng: &mut impl Rng) -> f32 {
+        if self.sensing_noise > 0.0 {
+            energy + rng.gen_range(-self.sensing_noise..=self.sensing_noise)
+        } else {
+            energy
+        }
     }
+
+    /// Energy photosynthesis gives to a cell at given coordinates on given iteration,
+    /// before the facing direction, shading and resources are taken into account
+    pub fn photosynthesis_yield(&self, x: usize, y: usize, iterations: usize) -> f32 {
+        self.photosynthesis_energy * self.light_multiplier(x, y, iterations)
+    }
+
+    /// Mutation chance (in %) on given iteration.
+    /// Follows the mutation season, if it is enabled
+    pub fn effective_mutation_percent(&self, iterations: usize) -> f64 {
+        match self.mutation_season_period {
+            Some(period) if period > 0 => {
+                let phase = (iterations % period) as f64 / period as f64;
+                // 0.0 at the start of the season, 1.0 in the middle
+                let intensity = (1.0 - (phase * std::f64::consts::TAU).cos()) / 2.0;
+                self.mutation_percent
+                    + (self.mutation_peak_percent - self.mutation_percent) * intensity
+            }
+            _ => self.mutation_percent,
+        }
+    }
+
     /// Cost of "biting" other cell
     /// You need to have 2x movement cost to attack
     pub fn attack_required_energy(&self) -> f32 {
         self.movement_cost * 2.
     }
 }
+
+fn check_range<T: PartialOrd + Debug>(
+    name: &str,
+    value: T,
+    range: RangeInclusive<T>,
+) -> Result<(), String> {
+    if range.contains(&value) {
+        Ok(())
+    } else {
+        Err(format!("{name} must be in range {range:?}, got {value:?}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    fn lit_config(light_axis: LightAxis) -> Config {
+        Config {
+            width: 10,
+            height: 10,
+            light_axis,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn light_follows_the_day_night_cycle() {
+        let config = Config {
+            day_night_period: Some(100),
+            night_light: 0.2,
+            day_light: 1.0,
+            ..lit_config(LightAxis::Y)
+        };
+
+        // Midnight at the start of the period, noon in the middle
+        assert_close(config.light_multiplier(0, 5, 0), 0.1);
+        assert_close(config.light_multiplier(0, 5, 25), 0.3);
+        assert_close(config.light_multiplier(0, 5, 50), 0.5);
+        assert_close(config.light_multiplier(0, 5, 100), 0.1);
+    }
+
+    #[test]
+    fn light_gets_brighter_along_y() {
+        let config = lit_config(LightAxis::Y);
+        assert_close(config.light_multiplier(3, 0, 0), 0.0);
+        assert_close(config.light_multiplier(3, 5, 0), 0.5);
+        assert_close(config.light_multiplier(7, 5, 0), 0.5);
+        assert_close(config.light_multiplier(3, 9, 0), 0.9);
+    }
+
+    #[test]
+    fn light_gets_brighter_along_x() {
+        let config = lit_config(LightAxis::X);
+        assert_close(config.light_multiplier(0, 3, 0), 0.0);
+        assert_close(config.light_multiplier(5, 3, 0), 0.5);
+        assert_close(config.light_multiplier(5, 7, 0), 0.5);
+        assert_close(config.light_multiplier(9, 3, 0), 0.9);
+    }
+
+    #[test]
+    fn radial_light_is_brightest_in_the_center() {
+        let config = lit_config(LightAxis::Radial);
+        let center = config.light_multiplier(5, 5, 0);
+        assert!(center > config.light_multiplier(7, 5, 0));
+        assert!(config.light_multiplier(7, 5, 0) > config.light_multiplier(9, 5, 0));
+        // Symmetric around the center
+        assert_close(
+            config.light_multiplier(0, 0, 0),
+            config.light_multiplier(9, 9, 0),
+        );
+        assert_close(config.light_multiplier(0, 0, 0), 0.1);
+    }
+
+    #[test]
+    fn phototaxis_tax_is_paid_only_towards_the_light() {
+        let config = Config {
+            phototaxis_cost: 2.0,
+            ..lit_config(LightAxis::Y)
+        };
+        assert_close(config.phototaxis_tax((0, 2), (0, 3)), 0.2);
+        assert_close(config.phototaxis_tax((0, 3), (0, 2)), 0.0);
+        assert_close(config.phototaxis_tax((0, 3), (1, 3)), 0.0);
+
+        assert_close(lit_config(LightAxis::Y).phototaxis_tax((0, 2), (0, 3)), 0.0);
+    }
+
+    #[test]
+    fn light_comes_from_the_axis() {
+        assert_eq!(
+            lit_config(LightAxis::Y).towards_light(3, 3),
+            Some(Direction::Down)
+        );
+        assert_eq!(
+            lit_config(LightAxis::X).towards_light(3, 3),
+            Some(Direction::Right)
+        );
+
+        let config = lit_config(LightAxis::Radial);
+        assert_eq!(config.towards_light(0, 4), Some(Direction::Right));
+        assert_eq!(config.towards_light(9, 5), Some(Direction::Left));
+        assert_eq!(config.towards_light(4, 0), Some(Direction::Down));
+        assert_eq!(config.towards_light(5, 9), Some(Direction::Up));
+        // An odd-sized map has a cell right in the center
+        let config = Config {
+            width: 9,
+            height: 9,
+            ..config
+        };
+        assert_eq!(config.towards_light(4, 4), None);
+    }
+}
@@ -1,9 +1,10 @@
 use crate::Config;
 
+use rand::Rng;
 use rand_derive2::RandGen;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize, RandGen)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, RandGen)]
 pub enum Direction {
     Left,
     Right,
@@ -12,40 +13,81 @@ pub enum Direction {
 }
 
 impl Direction {
-    // Applies directional movement to given coordinates
-    pub fn apply_direction(&self, x: usize, y: usize, config: &Config) -> (usize, usize) {
+    // Same as `generate_random()`, but takes the randomness from given generator
+    pub fn generate_random_with_rng(rng: &mut impl Rng) -> Self {
+        rng.gen()
+    }
+
+    // Applies directional movement to given coordinates.
+    // Returns None if the movement would cross the edge of an axis that doesn't wrap around
+    pub fn apply_direction(&self, x: usize, y: usize, config: &Config) -> Option<(usize, usize)> {
         match self {
             Self::Left => {
-                if x == 0 {
-                    (config.width - 1, y)
+                if x > 0 {
+                    Some((x - 1, y))
+                } else if config.wrap_x {
+                    Some((config.width - 1, y))
                 } else {
-                    (x - 1, y)
+                    None
                 }
             }
             Self::Right => {
-                if x == config.width - 1 {
-                    (0, y)
+                if x < config.width - 1 {
+                    Some((x + 1, y))
+                } else if config.wrap_x {
+                    Some((0, y))
                 } else {
-                    (x + 1, y)
+                    None
                 }
             }
             Self::Up => {
-                if y == 0 {
-                    (x, 0)
+                if y > 0 {
+                    Some((x, y - 1))
+                } else if config.wrap_y {
+                    Some((x, config.height - 1))
                 } else {
-                    (x, y - 1)
+                    None
                 }
             }
             Self::Down => {
-                if y == config.height - 1 {
-                    (x, config.height - 1)
+                if y < config.height - 1 {
+                    Some((x, y + 1))
+                } else if config.wrap_y {
+                    Some((x, 0))
                 } else {
-                    (x, y + 1)
+                    None
                 }
             }
         }
     }
 
+    // Same as `apply_direction()`, but never wraps around
+    pub fn apply_direction_bounded(
+        &self,
+        x: usize,
+        y: usize,
+        config: &Config,
+    ) -> Option<(usize, usize)> {
+        match self {
+            Self::Left => x.checked_sub(1).map(|x| (x, y)),
+            Self::Right => (x + 1 < config.width).then_some((x + 1, y)),
+            Self::Up => y.checked_sub(1).map(|y| (x, y)),
+            Self::Down => (y + 1 < config.height).then_some((x, y + 1)),
+        }
+    }
+
+    // Fraction of the photosynthesis energy a bot facing this direction gets,
+    // when photosynthesis is directional and the light comes from `towards_light`
+    pub fn light_exposure(&self, towards_light: Direction) -> f32 {
+        if *self == towards_light {
+            1.0
+        } else if *self == towards_light.opposite() {
+            0.25
+        } else {
+            0.5
+        }
+    }
+
     // 'Rotates' direction to the left, returning a new one
     pub fn left(&self) -> Self {
         match self {
@@ -65,4 +107,78 @@ impl Direction {
             Self::Down => Direction::Left,
         }
     }
+
+    // Direction pointing the other way
+    pub fn opposite(&self) -> Self {
+        match self {
+            Self::Left => Direction::Right,
+            Self::Right => Direction::Left,
+            Self::Up => Direction::Down,
+            Self::Down => Direction::Up,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(wrap_x: bool, wrap_y: bool) -> Config {
+        Config {
+            width: 4,
+            height: 3,
+            wrap_x,
+            wrap_y,
+            ..Default::default()
+        }
+    }
+
+    // Moves out of every edge of a 4x3 map
+    fn across_edges(config: &Config) -> [Option<(usize, usize)>; 4] {
+        [
+            Direction::Left.apply_direction(0, 1, config),
+            Direction::Right.apply_direction(3, 1, config),
+            Direction::Up.apply_direction(1, 0, config),
+            Direction::Down.apply_direction(1, 2, config),
+        ]
+    }
+
+    #[test]
+    fn wraps_on_both_axes() {
+        assert_eq!(
+            across_edges(&config(true, true)),
+            [Some((3, 1)), Some((0, 1)), Some((1, 2)), Some((1, 0))]
+        );
+    }
+
+    #[test]
+    fn wraps_horizontally_only() {
+        assert_eq!(
+            across_edges(&config(true, false)),
+            [Some((3, 1)), Some((0, 1)), None, None]
+        );
+    }
+
+    #[test]
+    fn wraps_vertically_only() {
+        assert_eq!(
+            across_edges(&config(false, true)),
+            [None, None, Some((1, 2)), Some((1, 0))]
+        );
+    }
+
+    #[test]
+    fn doesnt_wrap() {
+        assert_eq!(across_edges(&config(false, false)), [None; 4]);
+    }
+
+    #[test]
+    fn moves_inside_the_map() {
+        let config = config(false, false);
+        assert_eq!(
+            Direction::Right.apply_direction(1, 1, &config),
+            Some((2, 1))
+        );
+        assert_eq!(Direction::Up.apply_direction(1, 1, &config), Some((1, 0)));
+    }
 }
@@ -5,8 +5,9 @@ use serde::{Deserialize, Serialize};
 use crate::Config;
 
 use super::config;
+use super::config::{BranchMutationMode, CrossoverMode};
 
-#[derive(Default, Debug, RandGen, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, RandGen, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 // Enum for all possible instructions
 pub enum Instruction {
     // No operation. Speaks for itself
@@ -20,11 +21,19 @@ pub enum Instruction {
     TurnRight,
     // Moves forward in the bot's direction
     MoveForwards,
+    // Moves up to B1 cells forward at once, stopping in front of the first occupied cell.
+    // Costs movement energy for every cell traversed
+    Leap,
+    // Grows a tail into the cell behind, if `Config::multicell` is enabled.
+    // The bot and it's tail make up one body, see `bot::BodyLink`
+    Grow,
 
     // Makes energy through photosynthesis
     Photosynthesis,
     // Gives instruction.e% energy to cell in front
     GiveEnergy,
+    // Splits instruction.e energy equally between all alive neighbors (8 cells around)
+    ShareWithNeighbors,
 
     // Attacks cell in front, taking 50% of energy from it (can be configured)
     // If instruction.opt is true, kills the cell in front
@@ -34,6 +43,10 @@ pub enum Instruction {
 
     // Checks if energy is higher than instruction.b1, then jumps to B1, otherwise jumps to B2
     CheckEnergy,
+    // Writes bot's current energy into the energy field of gene at index B1.
+    // This lets the genome tune it's own thresholds, so note that the bot's behavior
+    // is no longer fixed at birth, and can drift during it's lifetime
+    SampleEnergyThreshold,
 
     // If cell is facing this direction, jumps to B1, otherwise to B2
     CheckIfDirectedLeft,
@@ -49,14 +62,263 @@ pub enum Instruction {
     CheckIfFacingVoid,
     // If bot is facing it's relative, jumps to B1, otherwise to B2
     // 'Relative' is a cell that has all the genes the same.
-    // Only instructions are checked, other fields are ignored
+    // Depending on `Config::kin_compare_mode`, either only instructions or whole genes are checked
     CheckIfFacingRelative,
+    // Looks instruction.e cells ahead (at least 1, at most `Config::vision_range`).
+    // If that cell is alive (instruction.opt is true) or empty (instruction.opt is false),
+    // jumps to B1, otherwise to B2
+    CheckAtDistance,
+    // If the last MoveForwards, AttackCell or MakeChild failed, jumps to B1, otherwise to B2
+    CheckLastFailed,
 
     // Reproduces. A certain minimum amount of energy is required to reproduced, can be configured.
     // If a child was made successfully, jumps to B1, otherwise to B2
     MakeChild,
 }
 
+impl Instruction {
+    /// Number of different instructions
+    pub const COUNT: usize = Self::ALL.len();
+
+    /// All instructions, in the order of declaration.
+    /// `Instruction::ALL[instruction as usize] == instruction`
+    pub const ALL: [Instruction; 24] = [
+        Self::Noop,
+        Self::TurnLeft,
+        Self::TurnRight,
+        Self::MoveForwards,
+        Self::Leap,
+        Self::Grow,
+        Self::Photosynthesis,
+        Self::GiveEnergy,
+        Self::ShareWithNeighbors,
+        Self::AttackCell,
+        Self::RecycleDeadCell,
+        Self::CheckEnergy,
+        Self::SampleEnergyThreshold,
+        Self::CheckIfDirectedLeft,
+        Self::CheckIfDirectedRight,
+        Self::CheckIfDirectedUp,
+        Self::CheckIfDirectedDown,
+        Self::CheckIfFacingAliveCell,
+        Self::CheckIfFacingDeadCell,
+        Self::CheckIfFacingVoid,
+        Self::CheckIfFacingRelative,
+        Self::CheckAtDistance,
+        Self::CheckLastFailed,
+        Self::MakeChild,
+    ];
+}
+
+impl Instruction {
+    /// Whether the instruction jumps to B1 or B2 instead of going to the next one
+    pub fn is_conditional(&self) -> bool {
+        matches!(
+            self,
+            Self::CheckEnergy
+                | Self::CheckIfDirectedLeft
+                | Self::CheckIfDirectedRight
+                | Self::CheckIfDirectedUp
+                | Self::CheckIfDirectedDown
+                | Self::CheckIfFacingAliveCell
+                | Self::CheckIfFacingDeadCell
+                | Self::CheckIfFacingVoid
+                | Self::CheckIfFacingRelative
+                | Self::CheckAtDistance
+                | Self::CheckLastFailed
+                | Self::MakeChild
+        )
+    }
+
+    /// Whether this is one of the Check* instructions, which only look at the bot or the world
+    pub fn is_check(&self) -> bool {
+        self.is_conditional() && *self != Self::MakeChild
+    }
+
+    /// Whether this instruction acts on the world or the bot's energy.
+    /// Bot stops executing instructions for the tick after one of these
+    pub fn is_action(&self) -> bool {
+        matches!(
+            self,
+            Self::MoveForwards
+                | Self::Leap
+                | Self::Grow
+                | Self::Photosynthesis
+                | Self::GiveEnergy
+                | Self::ShareWithNeighbors
+                | Self::AttackCell
+                | Self::RecycleDeadCell
+                | Self::MakeChild
+        )
+    }
+}
+
+/// Set of instructions, stored as a bitmask so that [`Config`] stays `Copy`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct InstructionSet(u32);
+
+impl InstructionSet {
+    pub const ALL: InstructionSet = InstructionSet((1 << Instruction::COUNT) - 1);
+
+    pub fn contains(&self, instruction: Instruction) -> bool {
+        self.0 & (1 << instruction as u32) != 0
+    }
+    pub fn set(&mut self, instruction: Instruction, enabled: bool) {
+        if enabled {
+            self.0 |= 1 << instruction as u32;
+        } else {
+            self.0 &= !(1 << instruction as u32);
+        }
+    }
+
+    /// Random instruction from this set, or `Noop` if it's empty
+    pub fn random(&self, rng: &mut impl Rng) -> Instruction {
+        Instruction::ALL
+            .into_iter()
+            .filter(|&instruction| self.contains(instruction))
+            .choose(rng)
+            .unwrap_or_default()
+    }
+}
+
+/// Which genes can ever be executed, starting from the first one.
+/// Follows the fall-through of regular instructions, and both branches of conditional ones
+pub fn reachable_instructions(
+    genome: &[Gene; config::GENOME_LENGTH as usize],
+) -> [bool; config::GENOME_LENGTH as usize] {
+    let mut reachable = [false; config::GENOME_LENGTH as usize];
+    let mut to_visit = vec![0];
+
+    while let Some(index) = to_visit.pop() {
+        // Instruction pointer wraps around to the start, just like in Bot::update()
+        let index = if index >= genome.len() { 0 } else { index };
+        if reachable[index] {
+            continue;
+        }
+        reachable[index] = true;
+
+        let gene = &genome[index];
+        if gene.instruction.is_conditional() {
+            to_visit.push(gene.branch as usize);
+            to_visit.push(gene.branch_alt as usize);
+        } else {
+            to_visit.push(index + 1);
+        }
+    }
+
+    reachable
+}
+
+/// Number of genes that can be executed and do something, i.e. reachable genes except `Noop`
+pub fn complexity(genome: &[Gene; config::GENOME_LENGTH as usize]) -> usize {
+    reachable_instructions(genome)
+        .iter()
+        .zip(genome.iter())
+        .filter(|&(&reachable, gene)| reachable && gene.instruction != Instruction::Noop)
+        .count()
+}
+
+/// Equivalent genome without the genes that can never be executed.
+/// Reachable genes are moved together and their branches are renumbered, the freed space is filled with `Noop`
+pub fn minimize_genome(
+    genome: &[Gene; config::GENOME_LENGTH as usize],
+) -> [Gene; config::GENOME_LENGTH as usize] {
+    let reachable = reachable_instructions(genome);
+
+    // The last gene falls through to the first one, so if it's reachable,
+    // the run of genes at the end stays in place, and the padding goes before it
+    let tail_start = (0..genome.len())
+        .rev()
+        .take_while(|&index| reachable[index])
+        .last()
+        .unwrap_or(genome.len());
+
+    let mut new_index = [0; config::GENOME_LENGTH as usize];
+    let mut head_length = 0;
+    for index in 0..genome.len() {
+        if index >= tail_start {
+            new_index[index] = index as u8;
+        } else if reachable[index] {
+            new_index[index] = head_length as u8;
+            head_length += 1;
+        }
+    }
+
+    let renumber = |branch: u8| match reachable.get(branch as usize) {
+        Some(true) => new_index[branch as usize],
+        // Only SampleEnergyThreshold can point at a removed gene.
+        // Writing to the padding instead keeps the kept genes untouched
+        Some(false) => head_length as u8,
+        // Out of bounds branches wrap to the first gene, which never moves
+        None => branch,
+    };
+
+    let mut minimized = [Gene::default(); config::GENOME_LENGTH as usize];
+    for (index, gene) in genome.iter().enumerate() {
+        if !reachable[index] {
+            continue;
+        }
+
+        let mut gene = *gene;
+        if gene.instruction.is_conditional() {
+            gene.branch = renumber(gene.branch);
+            gene.branch_alt = renumber(gene.branch_alt);
+        } else if gene.instruction == Instruction::SampleEnergyThreshold {
+            // B1 is an index of a gene here. Other instructions (e.g. Leap) use it as a number
+            gene.branch = renumber(gene.branch);
+        }
+        minimized[new_index[index] as usize] = gene;
+    }
+
+    minimized
+}
+
+/// Combine genomes of two parents. Each gene of the child comes from one of them, at the same index
+pub fn crossover(
+    first: &[Gene; config::GENOME_LENGTH as usize],
+    second: &[Gene; config::GENOME_LENGTH as usize],
+    mode: CrossoverMode,
+    rng: &mut impl Rng,
+) -> [Gene; config::GENOME_LENGTH as usize] {
+    let mut child = *first;
+    match mode {
+        CrossoverMode::SinglePoint => {
+            let point = rng.gen_range(0..=child.len());
+            child[point..].copy_from_slice(&second[point..]);
+        }
+        CrossoverMode::Uniform => {
+            for (gene, other) in child.iter_mut().zip(second.iter()) {
+                if rng.gen() {
+                    *gene = *other;
+                }
+            }
+        }
+    }
+    child
+}
+
+/// Generate a genome made of random genes
+pub fn random_genome(
+    rng: &mut impl Rng,
+    config: &Config,
+) -> [Gene; config::GENOME_LENGTH as usize] {
+    let mut genome = [Gene::default(); config::GENOME_LENGTH as usize];
+    for gene in genome.iter_mut() {
+        *gene = Gene::new_random_with_rng(rng, config);
+    }
+    genome
+}
+
+/// Export a genome as a string, that can be shared and imported back with [`genome_from_string`]
+pub fn genome_to_string(genome: &[Gene; config::GENOME_LENGTH as usize]) -> String {
+    serde_json::to_string(genome).expect("genome is always serializable")
+}
+
+/// Import a genome exported with [`genome_to_string`]
+pub fn genome_from_string(string: &str) -> Result<[Gene; config::GENOME_LENGTH as usize], String> {
+    serde_json::from_str(string).map_err(|error| format!("invalid genome: {error}"))
+}
+
 // Used in Gene::mutate() to determine which field to mutate
 #[derive(RandGen)]
 enum ThingToMutate {
@@ -67,7 +329,7 @@ enum ThingToMutate {
     BranchAlt,
 }
 
-#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Gene {
     pub instruction: Instruction,
 
@@ -84,9 +346,12 @@ pub struct Gene {
 impl Gene {
     // Create a new, randomly generated gene
     pub fn new_random(config: &Config) -> Self {
-        let mut rng = thread_rng();
+        Self::new_random_with_rng(&mut thread_rng(), config)
+    }
+    // Same as `new_random()`, but takes the randomness from given generator
+    pub fn new_random_with_rng(rng: &mut impl Rng, config: &Config) -> Self {
         Gene {
-            instruction: Instruction::generate_random(),
+            instruction: config.enabled_instructions.random(rng),
             option: rng.gen(),
             energy: rng.gen_range(0.0..config.reproduction_required_energy * 2.0),
             branch: rng.gen_range(0..config::GENOME_LENGTH),
@@ -96,15 +361,158 @@ impl Gene {
 
     // Mutate one of gene's fields randomly
     pub fn mutate(&mut self, config: &Config) {
-        let mut rng = thread_rng();
-        match ThingToMutate::generate_random() {
-            ThingToMutate::Instruction => self.instruction = Instruction::generate_random(),
+        self.mutate_with_rng(&mut thread_rng(), config)
+    }
+    // Same as `mutate()`, but takes the randomness from given generator
+    pub fn mutate_with_rng(&mut self, rng: &mut impl Rng, config: &Config) {
+        match rng.gen::<ThingToMutate>() {
+            ThingToMutate::Instruction => {
+                self.instruction = config.enabled_instructions.random(rng)
+            }
             ThingToMutate::Option => self.option = rng.gen(),
             ThingToMutate::Energy => {
                 self.energy = rng.gen_range(0.0..config.reproduction_required_energy * 2.0)
             }
-            ThingToMutate::Branch => self.branch = rng.gen_range(0..config::GENOME_LENGTH),
-            ThingToMutate::BranchAlt => self.branch_alt = rng.gen_range(0..config::GENOME_LENGTH),
+            ThingToMutate::Branch => self.branch = mutate_branch(rng, self.branch, config),
+            ThingToMutate::BranchAlt => {
+                self.branch_alt = mutate_branch(rng, self.branch_alt, config)
+            }
         };
     }
 }
+
+// New value of a mutated branch target, see `Config::branch_mutation_mode`
+fn mutate_branch(rng: &mut impl Rng, branch: u8, config: &Config) -> u8 {
+    match config.branch_mutation_mode {
+        BranchMutationMode::Uniform => rng.gen_range(0..config::GENOME_LENGTH),
+        BranchMutationMode::LocalJitter(amount) => {
+            // Delta is never 0, so that the mutation always changes something
+            let amount = amount.max(1) as i32;
+            let delta = if rng.gen() {
+                rng.gen_range(1..=amount)
+            } else {
+                -rng.gen_range(1..=amount)
+            };
+            (branch as i32 + delta).rem_euclid(config::GENOME_LENGTH as i32) as u8
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+
+    use super::*;
+
+    fn gene(instruction: Instruction, branch: u8, branch_alt: u8) -> Gene {
+        Gene {
+            instruction,
+            branch,
+            branch_alt,
+            ..Default::default()
+        }
+    }
+
+    // Checks energy, then jumps over genes 1..5, which can never be executed
+    fn genome_with_gap() -> [Gene; config::GENOME_LENGTH as usize] {
+        let mut genome = [Gene::default(); config::GENOME_LENGTH as usize];
+        genome[0] = gene(Instruction::CheckEnergy, 5, 5);
+        genome[2] = gene(Instruction::TurnLeft, 0, 0);
+        genome[5] = gene(Instruction::TurnRight, 0, 0);
+        genome
+    }
+
+    #[test]
+    fn minimize_genome_keeps_reachable_end_in_place() {
+        let genome = genome_with_gap();
+        let mut expected = genome;
+        expected[2] = Gene::default();
+
+        assert_eq!(minimize_genome(&genome), expected);
+    }
+
+    #[test]
+    fn minimize_genome_moves_reachable_genes_together() {
+        let mut genome = [Gene::default(); config::GENOME_LENGTH as usize];
+        genome[0] = gene(Instruction::CheckEnergy, 3, 3);
+        genome[1] = gene(Instruction::TurnLeft, 0, 0);
+        genome[3] = gene(Instruction::CheckIfFacingVoid, 0, 0);
+
+        let mut expected = [Gene::default(); config::GENOME_LENGTH as usize];
+        expected[0] = gene(Instruction::CheckEnergy, 1, 1);
+        expected[1] = gene(Instruction::CheckIfFacingVoid, 0, 0);
+
+        assert_eq!(minimize_genome(&genome), expected);
+    }
+
+    #[test]
+    fn minimize_genome_keeps_complexity() {
+        let config = Config::default();
+        for seed in 0..50 {
+            let genome = random_genome(&mut StdRng::seed_from_u64(seed), &config);
+            assert_eq!(complexity(&minimize_genome(&genome)), complexity(&genome));
+        }
+    }
+
+    // Genome of `Noop`s, told apart by the energy of their genes
+    fn marked_genome(energy: f32) -> [Gene; config::GENOME_LENGTH as usize] {
+        [Gene {
+            energy,
+            ..Default::default()
+        }; config::GENOME_LENGTH as usize]
+    }
+
+    #[test]
+    fn single_point_crossover_takes_start_of_one_parent_and_end_of_other() {
+        let (first, second) = (marked_genome(1.0), marked_genome(2.0));
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let child = crossover(&first, &second, CrossoverMode::SinglePoint, &mut rng);
+
+            let point = child
+                .iter()
+                .position(|gene| gene.energy == 2.0)
+                .unwrap_or(child.len());
+            assert_eq!(child[..point], first[..point]);
+            assert_eq!(child[point..], second[point..]);
+        }
+    }
+
+    #[test]
+    fn uniform_crossover_keeps_genes_at_their_index() {
+        let (mut first, mut second) = (marked_genome(1.0), marked_genome(2.0));
+        for index in 0..first.len() {
+            first[index].branch = index as u8;
+            second[index].branch = index as u8;
+        }
+
+        let child = crossover(
+            &first,
+            &second,
+            CrossoverMode::Uniform,
+            &mut StdRng::seed_from_u64(1),
+        );
+
+        for (index, gene) in child.iter().enumerate() {
+            assert!(*gene == first[index] || *gene == second[index]);
+        }
+        assert!(child.iter().any(|gene| gene.energy == 1.0));
+        assert!(child.iter().any(|gene| gene.energy == 2.0));
+    }
+
+    #[test]
+    fn complexity_counts_reachable_genes_except_noop() {
+        assert_eq!(
+            complexity(&[Gene::default(); config::GENOME_LENGTH as usize]),
+            0
+        );
+        // TurnLeft can't be reached
+        assert_eq!(complexity(&genome_with_gap()), 2);
+
+        let mut genome = [gene(Instruction::Photosynthesis, 0, 0); config::GENOME_LENGTH as usize];
+        assert_eq!(complexity(&genome), config::GENOME_LENGTH as usize);
+        // Loops on itself, so nothing after it is reachable
+        genome[1] = gene(Instruction::CheckEnergy, 1, 1);
+        assert_eq!(complexity(&genome), 2);
+    }
+}
@@ -1,10 +1,23 @@
+use std::collections::HashMap;
+
+#[derive(Clone)]
+enum Storage<T> {
+    /// Every cell is stored, column by column
+    Dense(Vec<Vec<T>>),
+    /// Only the cells that were set are stored
+    Sparse(HashMap<(usize, usize), T>),
+}
+
 /// A structure containing map with all the cells.
-/// It is just a wrapper around [`Vec`] with some helper methods.
+/// It is just a wrapper around [`Vec`] (or [`HashMap`], for sparse maps) with some helper methods.
 #[derive(Clone)]
 pub struct Map<T> {
-    map: Vec<Vec<T>>,
+    storage: Storage<T>,
     width: usize,
     height: usize,
+
+    /// Returned for cells that are missing from a sparse map
+    vacant: T,
 }
 
 impl<T> Default for Map<T>
@@ -30,7 +43,24 @@ where
             }
         }
 
-        Map { map, width, height }
+        Map {
+            storage: Storage::Dense(map),
+            width,
+            height,
+            vacant: T::default(),
+        }
+    }
+
+    /// Create a map that stores only the cells that were set,
+    /// every other cell reads as `T::default()`.
+    /// Uses a lot less memory for huge maps with few occupied cells.
+    pub fn new_sparse(width: usize, height: usize) -> Self {
+        Map {
+            storage: Storage::Sparse(HashMap::new()),
+            width,
+            height,
+            vacant: T::default(),
+        }
     }
 
     pub fn width(&self) -> usize {
@@ -39,17 +69,127 @@ where
     pub fn height(&self) -> usize {
         self.height
     }
+    pub fn is_sparse(&self) -> bool {
+        matches!(self.storage, Storage::Sparse(_))
+    }
 
     // Returns a cell at the specified coordinates
     pub fn get(&self, x: usize, y: usize) -> Option<&T> {
-        self.map.get(x)?.get(y)
+        match &self.storage {
+            Storage::Dense(map) => map.get(x)?.get(y),
+            Storage::Sparse(map) => {
+                if x >= self.width || y >= self.height {
+                    return None;
+                }
+                Some(map.get(&(x, y)).unwrap_or(&self.vacant))
+            }
+        }
     }
+    /// For sparse maps, this inserts a default cell if there was none
     pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut T> {
-        self.map.get_mut(x)?.get_mut(y)
+        match &mut self.storage {
+            Storage::Dense(map) => map.get_mut(x)?.get_mut(y),
+            Storage::Sparse(map) => {
+                if x >= self.width || y >= self.height {
+                    return None;
+                }
+                Some(map.entry((x, y)).or_default())
+            }
+        }
     }
 
     /// Set a cell at specified coordinates
     pub fn set(&mut self, x: usize, y: usize, cell: T) {
-        self.map[x][y] = cell;
+        match &mut self.storage {
+            Storage::Dense(map) => map[x][y] = cell,
+            Storage::Sparse(map) => {
+                assert!(
+                    x < self.width && y < self.height,
+                    "coordinates ({x}, {y}) are out of bounds"
+                );
+                map.insert((x, y), cell);
+            }
+        }
+    }
+
+    /// Coordinates of the 8 surrounding cells (Moore neighborhood).
+    /// Neighbors across an edge are included only if that axis wraps around
+    pub fn neighbors(
+        &self,
+        x: usize,
+        y: usize,
+        wrap_x: bool,
+        wrap_y: bool,
+    ) -> impl Iterator<Item = (usize, usize)> {
+        self.neighbors_within(x, y, 1, wrap_x, wrap_y)
+    }
+
+    /// Coordinates of all cells within `radius` (Chebyshev distance), except the center one.
+    /// Same as [`Map::neighbors`] for radius 1
+    pub fn neighbors_within(
+        &self,
+        x: usize,
+        y: usize,
+        radius: usize,
+        wrap_x: bool,
+        wrap_y: bool,
+    ) -> impl Iterator<Item = (usize, usize)> {
+        let (width, height) = (self.width, self.height);
+        let radius = radius as isize;
+
+        (-radius..=radius)
+            .flat_map(move |dx| (-radius..=radius).map(move |dy| (dx, dy)))
+            .filter(|&offset| offset != (0, 0))
+            .filter_map(move |(dx, dy)| {
+                let neighbor_x = wrap_coordinate(x as isize + dx, width, wrap_x)?;
+                let neighbor_y = wrap_coordinate(y as isize + dy, height, wrap_y)?;
+                Some((neighbor_x, neighbor_y))
+            })
+    }
+
+    /// Coordinates of all stored cells.
+    /// For a dense map, that's every cell, column by column.
+    /// For a sparse map, only the cells that were set, in the same order
+    pub fn stored_coordinates(&self) -> Vec<(usize, usize)> {
+        match &self.storage {
+            Storage::Dense(_) => (0..self.width)
+                .flat_map(|x| (0..self.height).map(move |y| (x, y)))
+                .collect(),
+            Storage::Sparse(map) => {
+                let mut coordinates: Vec<_> = map.keys().copied().collect();
+                coordinates.sort_unstable();
+                coordinates
+            }
+        }
+    }
+
+    /// Iterate over all stored cells, see [`Map::stored_coordinates`].
+    /// Unlike there, cells of a sparse map come in no particular order
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (usize, usize, &T)> + '_> {
+        match &self.storage {
+            Storage::Dense(map) => Box::new(map.iter().enumerate().flat_map(|(x, column)| {
+                column.iter().enumerate().map(move |(y, cell)| (x, y, cell))
+            })),
+            Storage::Sparse(map) => Box::new(map.iter().map(|(&(x, y), cell)| (x, y, cell))),
+        }
+    }
+
+    /// Drop cells that don't match the predicate from a sparse map,
+    /// so that they read as default again. Does nothing for a dense map
+    pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        if let Storage::Sparse(map) = &mut self.storage {
+            map.retain(|_, cell| f(cell));
+        }
+    }
+}
+
+// Brings coordinate back into `0..size`, or returns None if it's outside and can't wrap
+fn wrap_coordinate(coordinate: isize, size: usize, wrap: bool) -> Option<usize> {
+    if (0..size as isize).contains(&coordinate) {
+        Some(coordinate as usize)
+    } else if wrap {
+        Some(coordinate.rem_euclid(size as isize) as usize)
+    } else {
+        None
     }
 }
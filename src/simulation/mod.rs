@@ -4,22 +4,54 @@ pub mod config;
 pub mod direction;
 pub mod gene;
 pub mod map;
+pub mod save;
+pub mod stats;
 
-use bot::Bot;
+use std::collections::HashMap;
+
+use bot::{BodyLink, Bot};
+use color::Color;
+use gene::{Gene, Instruction};
 use map::Map;
 use rand::prelude::*;
+use save::SavedState;
+use stats::{ActionTally, EvolutionReport, StagnationDetector, Stats};
+
+use super::{Config, GENOME_LENGTH};
 
-use super::Config;
+/// Something notable that happened during an update, see [`Simulation::events`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SimulationEvent {
+    /// A member of the lineage has reproduced for the first time, establishing the lineage
+    FirstReproduction { lineage: u64, iteration: usize },
+}
 
 pub struct Simulation {
     width: usize,
     height: usize,
     iterations: usize,
     map: Map<Bot>,
+    stats: Stats,
+    // Found during the last update
+    max_energy_cell: Option<(usize, usize)>,
+    // Resource left on each cell, row by row, see `Config::resource_cap`.
+    // Empty until the first update with resources enabled
+    resources: Vec<f32>,
 
     selected_bot_coordinates: Option<(usize, usize)>,
     // Keep a copy of the bot even if it no longer exists on the map
     selected_bot: Option<Bot>,
+    // Second selection, compared against the first one in the genome diff
+    secondary_bot_coordinates: Option<(usize, usize)>,
+    secondary_bot: Option<Bot>,
+
+    // Iteration on which each lineage first reproduced. Not saved with the state
+    first_reproductions: HashMap<u64, usize>,
+    // Emitted during the last update
+    events: Vec<SimulationEvent>,
+    stagnation: StagnationDetector,
+    // All the randomness of the simulation comes from here, see `Simulation::new_seeded`
+    rng: StdRng,
 
     pub configuration: Config,
 }
@@ -28,6 +60,16 @@ impl Simulation {
     /// Create a new simulation with map of given width and height.
     /// Also calls `generate_map()` automatically.
     pub fn new(config: Option<Config>) -> Self {
+        Self::with_rng(config, StdRng::from_entropy())
+    }
+
+    /// Same as `new()`, but the run is reproducible:
+    /// simulations with the same config and seed go through the same states
+    pub fn new_seeded(config: Option<Config>, seed: u64) -> Self {
+        Self::with_rng(config, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(config: Option<Config>, rng: StdRng) -> Self {
         let config = config.unwrap_or_default();
 
         let mut simulation = Simulation {
@@ -35,8 +77,17 @@ impl Simulation {
             height: config.height,
             iterations: 0,
             map: Map::new(config.width, config.height),
+            stats: Stats::default(),
+            max_energy_cell: None,
+            resources: Vec::new(),
             selected_bot_coordinates: None,
             selected_bot: None,
+            secondary_bot_coordinates: None,
+            secondary_bot: None,
+            first_reproductions: HashMap::new(),
+            events: Vec::new(),
+            stagnation: StagnationDetector::default(),
+            rng,
             configuration: config,
         };
 
@@ -44,78 +95,1293 @@ impl Simulation {
         simulation
     }
 
-    pub fn generate_map(&mut self) {
-        let mut rng = thread_rng();
+    /// Create a simulation with the map loaded from an image.
+    /// The image is scaled and cropped to the map size, and each pixel becomes a cell:
+    /// black pixels are empty, gray ones are walls, and any other color is a random bot of that color
+    #[cfg(feature = "image")]
+    pub fn from_image(path: impl AsRef<std::path::Path>, config: Config) -> std::io::Result<Self> {
+        use image::imageops::FilterType;
+        use std::io::{Error, ErrorKind};
+
+        let image = image::open(path)
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))?
+            .resize_to_fill(
+                config.width as u32,
+                config.height as u32,
+                FilterType::Nearest,
+            )
+            .to_rgb8();
+
+        let mut simulation = Simulation::new(Some(config));
+        simulation.map = if config.sparse_map {
+            Map::new_sparse(config.width, config.height)
+        } else {
+            Map::new(config.width, config.height)
+        };
+
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let (x, y) = (x as usize, y as usize);
+            let [r, g, b] = pixel.0;
+            let brightest = r.max(g).max(b);
+            let darkest = r.min(g).min(b);
+
+            if brightest < 32 {
+                if !simulation.map.is_sparse() {
+                    simulation.map.set(x, y, Bot::new_empty(x, y));
+                }
+            } else if brightest - darkest < 16 && brightest < 224 {
+                simulation.map.set(x, y, Bot::new_wall(x, y));
+            } else {
+                let mut bot = Bot::new_random_with_rng(&mut simulation.rng, x, y, &config);
+                bot.color = Color::new(r, g, b);
+                simulation.map.set(x, y, bot);
+            }
+        }
+
+        simulation.stats = Stats::compute(&simulation.map, &config);
+        Ok(simulation)
+    }
+
+    /// Render the map to an image, one pixel per cell, with the original cell colors
+    #[cfg(feature = "image")]
+    pub fn render_to_image(&self) -> image::RgbImage {
+        let mut image = image::RgbImage::new(self.width as u32, self.height as u32);
+
+        for (x, y, bot) in self.map.iter() {
+            let color = if bot.wall {
+                Color::new(170, 170, 210)
+            } else if bot.alive {
+                bot.color
+            } else if bot.is_dead() {
+                Color::new(100, 100, 100)
+            } else {
+                continue;
+            };
+
+            image.put_pixel(
+                x as u32,
+                y as u32,
+                image::Rgb([color.r(), color.g(), color.b()]),
+            );
+        }
+
+        image
+    }
+
+    /// Render the map as text, one character per cell and one line per row.
+    /// Empty cells are spaces, dead ones are `.`, walls are `#`,
+    /// and alive ones are `o`, `O` or `@`, from the least to the most energetic
+    pub fn to_ascii(&self) -> String {
+        let reproduction_energy = self.configuration.reproduction_required_energy;
+        let mut ascii = String::with_capacity((self.width + 1) * self.height);
+
         for y in 0..self.height {
             for x in 0..self.width {
-                // 20% chance to generate an alive bot
-                let cell_is_alive = rng.gen_bool(1.0 / 5.0);
-
-                let bot = if cell_is_alive {
-                    Bot::new_random(x, y, &self.configuration)
-                } else {
-                    Bot::new_empty(x, y)
+                // Cells missing from a sparse map are empty
+                let character = match self.map.get(x, y) {
+                    Some(bot) if bot.wall => '#',
+                    Some(bot) if bot.alive => {
+                        if bot.energy < reproduction_energy {
+                            'o'
+                        } else if bot.energy < reproduction_energy * 5. {
+                            'O'
+                        } else {
+                            '@'
+                        }
+                    }
+                    Some(bot) if bot.is_dead() => '.',
+                    _ => ' ',
                 };
+                ascii.push(character);
+            }
+            ascii.push('\n');
+        }
+
+        ascii
+    }
+
+    /// Full state of the simulation, to be saved with [`save::save_to_path`]
+    pub fn saved_state(&self) -> SavedState {
+        SavedState {
+            config: self.configuration,
+            iterations: self.iterations,
+            cells: self
+                .map
+                .iter()
+                .filter(|(_, _, bot)| !bot.empty)
+                .map(|(x, y, bot)| {
+                    // Cells missing from a sparse map don't know their coordinates
+                    let mut bot = *bot;
+                    bot.set_coordinates(x, y);
+                    bot
+                })
+                .collect(),
+            resources: self.resources.clone(),
+        }
+    }
+
+    /// How the population changed between two snapshots from [`Simulation::saved_state`]
+    pub fn compare_snapshots(before: &SavedState, after: &SavedState) -> EvolutionReport {
+        EvolutionReport::compare(before, after)
+    }
 
-                self.map.set(x, y, bot);
+    /// Restore a simulation from the state loaded with [`save::load_from_path`]
+    pub fn from_saved_state(state: SavedState) -> Self {
+        let config = state.config;
+
+        let map = if config.sparse_map {
+            Map::new_sparse(config.width, config.height)
+        } else {
+            let mut map = Map::new(config.width, config.height);
+            for x in 0..config.width {
+                for y in 0..config.height {
+                    map.set(x, y, Bot::new_empty(x, y));
+                }
             }
+            map
+        };
+        // Not `Simulation::new()`, since the map it generates would be thrown away
+        let mut simulation = Simulation {
+            width: config.width,
+            height: config.height,
+            iterations: state.iterations,
+            map,
+            stats: Stats::default(),
+            max_energy_cell: None,
+            // Refilled on the next update if the size doesn't match, e.g. in older saves
+            resources: state.resources,
+            selected_bot_coordinates: None,
+            selected_bot: None,
+            secondary_bot_coordinates: None,
+            secondary_bot: None,
+            first_reproductions: HashMap::new(),
+            events: Vec::new(),
+            stagnation: StagnationDetector::default(),
+            rng: StdRng::from_entropy(),
+            configuration: config,
+        };
+
+        for bot in state.cells {
+            simulation.set_cell(bot.x, bot.y, bot);
         }
+
+        simulation.stats = Stats::compute(&simulation.map, &config);
+        simulation
     }
+
+    pub fn generate_map(&mut self) {
+        self.map = if self.configuration.sparse_map {
+            Map::new_sparse(self.width, self.height)
+        } else {
+            Map::new(self.width, self.height)
+        };
+
+        let rng = &mut self.rng;
+        // Each spawned bot picks one of these, if the pool is enabled
+        let genome_pool: Vec<_> = (0..self.configuration.initial_genome_pool.unwrap_or(0))
+            .map(|_| {
+                let genome = gene::random_genome(rng, &self.configuration);
+                let color: Color = rng.gen();
+                (genome, color)
+            })
+            .collect();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let spawn_chance =
+                    self.configuration
+                        .seeding_pattern
+                        .spawn_chance(x, y, self.width, self.height);
+                let cell_is_alive = rng.gen_bool(spawn_chance);
+
+                if cell_is_alive {
+                    let mut bot = Bot::new_random_with_rng(rng, x, y, &self.configuration);
+                    if let Some(&(genome, color)) = genome_pool.choose(rng) {
+                        bot.genome = genome;
+                        bot.color = color;
+                        bot.refresh_fingerprint();
+                    }
+                    self.map.set(x, y, bot);
+                } else if !self.map.is_sparse() {
+                    self.map.set(x, y, Bot::new_empty(x, y));
+                }
+            }
+        }
+
+        self.stats = Stats::compute(&self.map, &self.configuration);
+    }
+    /// Generate a new map. The selection is cleared,
+    /// because the selected bot doesn't exist on the new map
     pub fn reset(&mut self) {
         self.iterations = 0;
+        self.max_energy_cell = None;
+        self.resources.clear();
+        self.selected_bot_coordinates = None;
+        self.selected_bot = None;
+        self.secondary_bot_coordinates = None;
+        self.secondary_bot = None;
+        self.first_reproductions.clear();
+        self.events.clear();
+        self.stagnation.reset();
         self.generate_map();
     }
+    /// Place up to `count` random bots on random empty cells.
+    /// Returns how many were placed, which is less than `count` if the map is too full
+    pub fn inject_random(&mut self, count: usize) -> usize {
+        let empty_cells = self.random_empty_cells(count);
+
+        for &(x, y) in empty_cells.iter() {
+            self.map.set(
+                x,
+                y,
+                Bot::new_random_with_rng(&mut self.rng, x, y, &self.configuration),
+            );
+        }
+
+        self.stats = Stats::compute(&self.map, &self.configuration);
+        empty_cells.len()
+    }
+    /// Place up to `count` copies of a genome on random empty cells, like `inject_random()`.
+    /// The copies share one lineage and color, so they can be told apart from the natives
+    pub fn inject_genome(&mut self, genome: [Gene; GENOME_LENGTH as usize], count: usize) -> usize {
+        let empty_cells = self.random_empty_cells(count);
+
+        let template =
+            Bot::new_with_genome_and_rng(&mut self.rng, 0, 0, genome, &self.configuration);
+        for &(x, y) in empty_cells.iter() {
+            let mut bot =
+                Bot::new_with_genome_and_rng(&mut self.rng, x, y, genome, &self.configuration);
+            bot.lineage = template.lineage;
+            bot.color = template.color;
+            self.map.set(x, y, bot);
+        }
+
+        self.stats = Stats::compute(&self.map, &self.configuration);
+        empty_cells.len()
+    }
+    // Up to `count` empty cells, in random order
+    fn random_empty_cells(&mut self, count: usize) -> Vec<(usize, usize)> {
+        let mut empty_cells: Vec<(usize, usize)> = (0..self.width)
+            .flat_map(|x| (0..self.height).map(move |y| (x, y)))
+            .filter(|&(x, y)| self.map.get(x, y).is_some_and(|cell| cell.empty))
+            .collect();
+
+        empty_cells.shuffle(&mut self.rng);
+        empty_cells.truncate(count);
+        empty_cells
+    }
+
+    /// Move all alive bots to random empty cells, keeping their genomes, colors and lineages.
+    /// Energy, age and direction are reset like for newly spawned bots.
+    /// Dead cells are removed, walls stay in place
+    pub fn reshuffle(&mut self) {
+        let bots: Vec<Bot> = self.living_bots().map(|(_, _, bot)| *bot).collect();
+
+        for (x, y) in self.map.stored_coordinates() {
+            if !self.map.get(x, y).is_some_and(|cell| cell.wall) {
+                self.map.set(x, y, Bot::new_empty(x, y));
+            }
+        }
+        self.map.retain(|bot| !bot.empty);
+
+        let empty_cells = self.random_empty_cells(bots.len());
+
+        for (bot, &(x, y)) in bots.iter().zip(empty_cells.iter()) {
+            let mut new_bot =
+                Bot::new_with_genome_and_rng(&mut self.rng, x, y, bot.genome, &self.configuration);
+            new_bot.color = bot.color;
+            new_bot.lineage = bot.lineage;
+            self.map.set(x, y, new_bot);
+        }
+
+        self.max_energy_cell = None;
+        self.selected_bot_coordinates = None;
+        self.selected_bot = None;
+        self.secondary_bot_coordinates = None;
+        self.secondary_bot = None;
+        self.stats = Stats::compute(&self.map, &self.configuration);
+    }
+
     pub fn iterations(&self) -> usize {
         self.iterations
     }
     pub fn map(&self) -> &Map<Bot> {
         &self.map
     }
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+    /// Resource left on each cell, row by row (`y * width + x`).
+    /// None if resources are disabled, see [`Config::resource_cap`]
+    pub fn resources(&self) -> Option<&[f32]> {
+        match self.configuration.resource_cap {
+            Some(_) if !self.resources.is_empty() => Some(&self.resources),
+            _ => None,
+        }
+    }
+    /// Coordinates of the alive bot with the most energy, as of the last update
+    pub fn max_energy_cell(&self) -> Option<(usize, usize)> {
+        self.max_energy_cell
+    }
+    /// Number of distinct species currently alive,
+    /// as defined by [`Config::species_definition`]
+    pub fn species_count(&self) -> usize {
+        self.stats.species_count
+    }
+
+    /// Replace a cell on the map, fixing up it's coordinates
+    pub fn set_cell(&mut self, x: usize, y: usize, mut bot: Bot) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+
+        bot.set_coordinates(x, y);
+        // The genome could have been edited by hand
+        bot.refresh_fingerprint();
+        self.map.set(x, y, bot);
+    }
+
+    /// How many times each instruction occurs in genomes of all alive bots,
+    /// indexed by `instruction as usize`
+    pub fn instruction_histogram(&self) -> [usize; Instruction::COUNT] {
+        let mut histogram = [0; Instruction::COUNT];
+
+        for (_, _, bot) in self.map.iter() {
+            if !bot.alive || bot.is_tail() {
+                continue;
+            }
+
+            for gene in bot.genome.iter() {
+                histogram[gene.instruction as usize] += 1;
+            }
+        }
+
+        histogram
+    }
+
+    /// Average [`gene::complexity`] of the genomes of alive bots. 0 if nobody is alive
+    pub fn average_complexity(&self) -> f32 {
+        let (count, total) = self
+            .living_bots()
+            .fold((0, 0), |(count, total), (_, _, bot)| {
+                (count + 1, total + gene::complexity(&bot.genome))
+            });
+
+        if count == 0 {
+            0.0
+        } else {
+            total as f32 / count as f32
+        }
+    }
+
+    /// Genome shared by the largest number of alive bots,
+    /// compared by their instructions. `None` if nobody is alive
+    pub fn dominant_genome(&self) -> Option<[Gene; GENOME_LENGTH as usize]> {
+        self.dominant_species().map(|(_, genome)| genome)
+    }
+
+    // Number of bots sharing the dominant genome, and the genome itself
+    fn dominant_species(&self) -> Option<(usize, [Gene; GENOME_LENGTH as usize])> {
+        // Fingerprint -> (number of bots, genome of one of them)
+        let mut lineages: HashMap<u64, (usize, [Gene; GENOME_LENGTH as usize])> = HashMap::new();
+
+        for (_, _, bot) in self.map.iter() {
+            if !bot.alive || bot.is_tail() {
+                continue;
+            }
+
+            lineages
+                .entry(bot.instruction_fingerprint())
+                .or_insert((0, bot.genome))
+                .0 += 1;
+        }
+
+        lineages.into_values().max_by_key(|&(count, _)| count)
+    }
+
+    // Sample the population for the stagnation detector
+    fn observe_stagnation(&mut self) {
+        let alive = self.stats.alive_count;
+        if alive == 0 {
+            // Extinction isn't an equilibrium, there's nothing left to evolve
+            self.stagnation.reset();
+            return;
+        }
+
+        let dominant_share = self
+            .dominant_species()
+            .map_or(0.0, |(count, _)| count as f32 / alive as f32);
+        let genes = (alive * GENOME_LENGTH as usize) as f32;
+        let instruction_shares = self
+            .instruction_histogram()
+            .map(|count| count as f32 / genes);
+
+        self.stagnation.observe(
+            dominant_share,
+            instruction_shares,
+            self.iterations,
+            &self.configuration,
+        );
+    }
+
+    /// Number of alive bots in each of `buckets` equal energy ranges from 0 to `max`.
+    /// Bots with more energy than `max` are counted in the last bucket
+    pub fn energy_histogram(&self, buckets: usize, max: f32) -> Vec<usize> {
+        let mut histogram = vec![0; buckets];
+        if buckets == 0 {
+            return histogram;
+        }
+
+        for (_, _, bot) in self.map.iter() {
+            if !bot.alive || bot.is_tail() {
+                continue;
+            }
+
+            let bucket = (bot.energy / max * buckets as f32) as usize;
+            histogram[bucket.min(buckets - 1)] += 1;
+        }
+
+        histogram
+    }
+
+    /// All alive bots with their coordinates, regardless of how the map is stored.
+    /// This is the supported way to compute custom metrics on the current state.
+    /// The same is available on a copy of the map, see [`living_bots`]
+    pub fn living_bots(&self) -> impl Iterator<Item = (usize, usize, &Bot)> {
+        living_bots(&self.map)
+    }
+
+    /// Coordinates of all alive bots descending from given lineage
+    pub fn find_by_lineage(&self, lineage: u64) -> Vec<(usize, usize)> {
+        find_by_lineage(&self.map, lineage)
+    }
 
     pub fn select_bot(&mut self, x: usize, y: usize) -> Option<Bot> {
         self.selected_bot_coordinates = Some((x, y));
-        let bot = *self.map.get(x, y)?;
+        let mut bot = *self.map.get(x, y)?;
+        // Cells missing from a sparse map don't know their coordinates
+        bot.set_coordinates(x, y);
         self.selected_bot = Some(bot);
         Some(bot)
     }
     pub fn selected_bot(&self) -> Option<Bot> {
         self.selected_bot
     }
+    /// Freeze or unfreeze the selected bot, wherever it is now.
+    /// Returns false if there is no alive bot selected
+    pub fn set_selected_frozen(&mut self, frozen: bool) -> bool {
+        let Some((x, y)) = self.selected_bot_coordinates else {
+            return false;
+        };
+        let Some(bot) = self.map.get_mut(x, y).filter(|bot| bot.alive) else {
+            return false;
+        };
+
+        bot.frozen = frozen;
+        self.selected_bot = Some(*bot);
+        true
+    }
+    /// Same as `select_bot()`, but for the secondary selection
+    pub fn select_secondary_bot(&mut self, x: usize, y: usize) -> Option<Bot> {
+        self.secondary_bot_coordinates = Some((x, y));
+        let mut bot = *self.map.get(x, y)?;
+        bot.set_coordinates(x, y);
+        self.secondary_bot = Some(bot);
+        Some(bot)
+    }
+    pub fn secondary_bot(&self) -> Option<Bot> {
+        self.secondary_bot
+    }
+
+    /// Iteration on which a member of the lineage reproduced for the first time,
+    /// `None` if it never has
+    pub fn first_reproduction(&self, lineage: u64) -> Option<usize> {
+        self.first_reproductions.get(&lineage).copied()
+    }
+
+    /// Events emitted during the last update, in order
+    pub fn events(&self) -> &[SimulationEvent] {
+        &self.events
+    }
+
+    /// Number of alive bots around given coordinates
+    fn alive_neighbors(&self, x: usize, y: usize) -> usize {
+        self.map
+            .neighbors(x, y, self.configuration.wrap_x, self.configuration.wrap_y)
+            .filter(|&(x, y)| self.map.get(x, y).is_some_and(|bot| bot.alive))
+            .count()
+    }
+
+    /// Split the energy of a bot that has just died between it's alive neighbors.
+    /// Without any, the energy stays in the corpse, or is lost if the bot vanished
+    fn release_energy(&mut self, bot: &mut Bot) {
+        let neighbors: Vec<(usize, usize)> = self
+            .map
+            .neighbors(
+                bot.x(),
+                bot.y(),
+                self.configuration.wrap_x,
+                self.configuration.wrap_y,
+            )
+            .filter(|&(x, y)| {
+                self.map
+                    .get(x, y)
+                    .is_some_and(|cell| cell.alive && !cell.is_tail())
+            })
+            .collect();
+
+        if !neighbors.is_empty() && bot.energy > 0.0 {
+            let share = bot.energy / neighbors.len() as f32;
+            for &(x, y) in neighbors.iter() {
+                self.map.get_mut(x, y).unwrap().energy += share;
+            }
+            bot.energy = 0.0;
+        }
+        if bot.empty {
+            bot.energy = 0.0;
+        }
+    }
+
+    /// Finish the death of a bot whose cell was taken by a child,
+    /// see [`Config::reproduction_overtakes`]
+    fn bury_displaced(&mut self, mut victim: Bot) {
+        if self.configuration.death_energy_to_neighbors {
+            self.release_energy(&mut victim);
+        }
+
+        // The cell now holds the child, so the selection stays with the dead bot
+        if self.selected_bot_coordinates == Some(victim.coordinates()) {
+            self.selected_bot_coordinates = None;
+            self.selected_bot = Some(victim);
+        }
+        if self.secondary_bot_coordinates == Some(victim.coordinates()) {
+            self.secondary_bot_coordinates = None;
+            self.secondary_bot = Some(victim);
+        }
+    }
+
+    /// Whether given coordinates are within `edge_kill_margin` of an edge.
+    /// Edges of axes that wrap around are ignored
+    fn in_kill_zone(&self, x: usize, y: usize) -> bool {
+        let margin = self.configuration.edge_kill_margin;
+        let near_x_edge = x < margin || x >= self.width.saturating_sub(margin);
+        let near_y_edge = y < margin || y >= self.height.saturating_sub(margin);
+
+        (near_x_edge && !self.configuration.wrap_x) || (near_y_edge && !self.configuration.wrap_y)
+    }
 
     /// Updates the simulation
     pub fn update(&mut self) {
-        for x in 0..self.width {
-            for y in 0..self.height {
-                let mut bot = *self.map.get(x, y).unwrap();
-                let orig_pos = bot.coordinates();
-
-                let mut config = *&self.configuration;
-                config.photosynthesis_energy =
-                    config.photosynthesis_energy * (y as f32 / config.height as f32);
-
-                bot.update(&mut self.map, &config);
-
-                // if bot position was changed, set empty cell at previous position
-                if orig_pos != bot.coordinates() {
-                    self.map.set(
-                        orig_pos.0,
-                        orig_pos.1,
-                        Bot::new_empty(orig_pos.0, orig_pos.1),
-                    );
+        let mut max_energy: Option<(f32, (usize, usize))> = None;
+        let mut actions = ActionTally::default();
+        self.events.clear();
+
+        if let Some(cap) = self.configuration.resource_cap {
+            // Resources start full
+            if self.resources.len() != self.width * self.height {
+                self.resources = vec![cap; self.width * self.height];
+            }
+            for resource in self.resources.iter_mut() {
+                *resource = (*resource + self.configuration.resource_regrowth).min(cap);
+            }
+        }
+
+        // Only the cells occupied before the tick are updated, so that a bot moving
+        // further along the map isn't updated twice. Dense and sparse maps behave the same
+        let occupied: Vec<(usize, usize)> = self
+            .map
+            .stored_coordinates()
+            .into_iter()
+            .filter(|&(x, y)| !self.map.get(x, y).unwrap().empty)
+            .collect();
+        for (x, y) in occupied {
+            let mut bot = *self.map.get(x, y).unwrap();
+            let orig_pos = bot.coordinates();
+            let was_alive = bot.alive;
+
+            // Tails are moved by their heads, and disappear once the head is gone
+            if let Some(BodyLink::Head(head_x, head_y)) = bot.body {
+                if !bot::is_attached(&self.map, (head_x, head_y), orig_pos) {
+                    self.map.set(x, y, Bot::new_empty(x, y));
+                }
+                continue;
+            }
+
+            if bot.alive && self.in_kill_zone(x, y) {
+                bot.die(&self.configuration);
+            }
+
+            if let Some(max_neighbors) = self.configuration.overcrowd_death {
+                if bot.alive && self.alive_neighbors(x, y) > max_neighbors {
+                    bot.die(&self.configuration);
                 }
+            }
 
-                // Update coordinates of the selected bot
-                if let Some(selected_bot_coordinates) = self.selected_bot_coordinates {
-                    if selected_bot_coordinates == orig_pos {
-                        self.selected_bot_coordinates = Some(bot.coordinates());
-                        self.selected_bot = Some(bot);
+            let mut config = self.configuration;
+            config.photosynthesis_energy = config.photosynthesis_yield(x, y, self.iterations);
+            config.mutation_percent = config.effective_mutation_percent(self.iterations);
+            let resource = self
+                .configuration
+                .resource_cap
+                .and_then(|_| self.resources.get_mut(y * self.width + x));
+            if let Some(resource) = &resource {
+                config.photosynthesis_energy = config.photosynthesis_energy.min(**resource);
+            }
+
+            let photosynthesized = actions.photosynthesized;
+            let reproduced = actions.reproduced;
+            let displaced = bot.update(&mut self.map, &config, &mut actions, &mut self.rng);
+
+            if actions.reproduced > reproduced
+                && !self.first_reproductions.contains_key(&bot.lineage)
+            {
+                self.first_reproductions
+                    .insert(bot.lineage, self.iterations);
+                self.events.push(SimulationEvent::FirstReproduction {
+                    lineage: bot.lineage,
+                    iteration: self.iterations,
+                });
+            }
+            // Bot photosynthesizes at most once per tick, drawing the whole yield of it's cell.
+            // Exposure and shading only limit how much of it the bot absorbs
+            if let Some(resource) = resource {
+                if actions.photosynthesized > photosynthesized {
+                    *resource -= config.photosynthesis_energy;
+                }
+            }
+
+            // if bot position was changed, set empty cell at previous position,
+            // unless the bot's tail has been dragged there
+            let tail_followed = self
+                .map
+                .get(orig_pos.0, orig_pos.1)
+                .is_some_and(|cell| cell.body == Some(BodyLink::Head(bot.x(), bot.y())));
+            if orig_pos != bot.coordinates() && !tail_followed {
+                self.map.set(
+                    orig_pos.0,
+                    orig_pos.1,
+                    Bot::new_empty(orig_pos.0, orig_pos.1),
+                );
+            }
+
+            // The tail dies together with the head
+            if !bot.alive {
+                if let Some((tail_x, tail_y)) = bot.tail() {
+                    let attached = self
+                        .map
+                        .get(tail_x, tail_y)
+                        .is_some_and(|cell| cell.body == Some(BodyLink::Head(bot.x(), bot.y())));
+                    if attached {
+                        self.map.set(tail_x, tail_y, Bot::new_empty(tail_x, tail_y));
                     }
                 }
+                bot.body = None;
+            }
 
-                self.map.set(bot.x(), bot.y(), bot);
+            // After the move, so that the bot's old cell doesn't count as a neighbor
+            if was_alive && !bot.alive && self.configuration.death_energy_to_neighbors {
+                self.release_energy(&mut bot);
+            }
+
+            // Update coordinates of the selected bot
+            if let Some(selected_bot_coordinates) = self.selected_bot_coordinates {
+                if selected_bot_coordinates == orig_pos {
+                    self.selected_bot_coordinates = Some(bot.coordinates());
+                    self.selected_bot = Some(bot);
+                }
+            }
+            if self.secondary_bot_coordinates == Some(orig_pos) {
+                self.secondary_bot_coordinates = Some(bot.coordinates());
+                self.secondary_bot = Some(bot);
+            }
+
+            if bot.alive && !max_energy.is_some_and(|(energy, _)| bot.energy <= energy) {
+                max_energy = Some((bot.energy, bot.coordinates()));
+            }
+
+            self.map.set(bot.x(), bot.y(), bot);
+            // After the parent is back on the map, so that it gets it's share of the energy
+            if let Some(victim) = displaced {
+                self.bury_displaced(victim);
             }
         }
+        self.max_energy_cell = max_energy.map(|(_, coordinates)| coordinates);
+        // Cells that became empty don't need to be stored in a sparse map
+        self.map.retain(|bot| !bot.empty);
 
+        self.stats = Stats::compute(&self.map, &self.configuration);
+        self.stats.actions = actions;
+        if self.configuration.stagnation_window.is_none() {
+            self.stagnation.reset();
+        } else if self.iterations >= self.stagnation.next_sample() {
+            self.observe_stagnation();
+        }
+        self.stats.stagnant = self.stagnation.is_stagnant();
         self.iterations += 1;
     }
 }
+
+/// See [`Simulation::living_bots`]
+pub fn living_bots(map: &Map<Bot>) -> impl Iterator<Item = (usize, usize, &Bot)> {
+    map.iter().filter(|(_, _, bot)| bot.alive && !bot.is_tail())
+}
+
+/// See [`Simulation::find_by_lineage`]
+pub fn find_by_lineage(map: &Map<Bot>, lineage: u64) -> Vec<(usize, usize)> {
+    living_bots(map)
+        .filter(|(_, _, bot)| bot.lineage == lineage)
+        .map(|(x, y, _)| (x, y))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_config() -> Config {
+        Config {
+            width: 8,
+            height: 6,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn saved_state_round_trip_keeps_cells_and_resources() {
+        let config = Config {
+            resource_cap: Some(5.0),
+            ..small_config()
+        };
+        let mut simulation = Simulation::new(Some(config));
+        simulation.update();
+        let state = simulation.saved_state();
+        assert_eq!(state.resources.len(), config.width * config.height);
+
+        let restored = Simulation::from_saved_state(state);
+
+        assert_eq!(restored.iterations(), simulation.iterations());
+        assert_eq!(restored.resources, simulation.resources);
+        assert_eq!(
+            restored.saved_state().cells.len(),
+            simulation.saved_state().cells.len()
+        );
+    }
+
+    #[test]
+    fn moving_bot_is_updated_once_with_any_storage() {
+        for sparse_map in [false, true] {
+            let config = Config {
+                sparse_map,
+                initial_direction: Some(direction::Direction::Right),
+                ..small_config()
+            };
+            let mut genome = [Gene::default(); GENOME_LENGTH as usize];
+            for gene in genome.iter_mut() {
+                gene.instruction = Instruction::MoveForwards;
+            }
+            let state = SavedState {
+                config,
+                iterations: 0,
+                cells: vec![Bot::new_with_genome(0, 0, genome, &config)],
+                resources: Vec::new(),
+            };
+            let mut simulation = Simulation::from_saved_state(state);
+
+            simulation.update();
+
+            let moved: Vec<_> = simulation.living_bots().map(|(x, y, _)| (x, y)).collect();
+            assert_eq!(moved, vec![(1, 0)], "sparse: {sparse_map}");
+        }
+    }
+
+    // Head at (1, 0) facing right, that always moves forwards, with it's tail at (0, 0)
+    fn body_simulation(head_energy: f32) -> Simulation {
+        let config = Config {
+            multicell: true,
+            initial_direction: Some(direction::Direction::Right),
+            ..small_config()
+        };
+        let mut genome = [Gene::default(); GENOME_LENGTH as usize];
+        for gene in genome.iter_mut() {
+            gene.instruction = Instruction::MoveForwards;
+        }
+        let mut head = Bot::new_with_genome(1, 0, genome, &config);
+        head.energy = head_energy;
+        head.body = Some(BodyLink::Tail(0, 0));
+        let mut tail = head;
+        tail.set_coordinates(0, 0);
+        tail.energy = 0.0;
+        tail.body = Some(BodyLink::Head(1, 0));
+
+        Simulation::from_saved_state(SavedState {
+            config,
+            iterations: 0,
+            cells: vec![head, tail],
+            resources: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn tail_follows_moving_head() {
+        let mut simulation = body_simulation(100.0);
+
+        simulation.update();
+
+        let head = simulation.map().get(2, 0).unwrap();
+        assert!(head.alive);
+        assert_eq!(head.tail(), Some((1, 0)));
+        assert_eq!(
+            simulation.map().get(1, 0).unwrap().body,
+            Some(BodyLink::Head(2, 0))
+        );
+        assert!(simulation.map().get(0, 0).unwrap().empty);
+        // The tail isn't counted as a bot of it's own
+        assert_eq!(simulation.stats().alive_count, 1);
+    }
+
+    #[test]
+    fn tail_dies_with_head() {
+        let mut simulation = body_simulation(-100.0);
+
+        simulation.update();
+
+        assert_eq!(simulation.living_bots().count(), 0);
+        assert!(simulation.map().iter().all(|(_, _, cell)| !cell.alive));
+    }
+
+    // Position, direction and energy of every alive bot
+    fn trace(simulation: &Simulation) -> Vec<(usize, usize, direction::Direction, f32)> {
+        simulation
+            .living_bots()
+            .map(|(x, y, bot)| (x, y, bot.direction, bot.energy))
+            .collect()
+    }
+
+    #[test]
+    fn minimized_genome_behaves_the_same() {
+        // Without mutations, the only randomness left is in the genomes
+        let config = Config {
+            mutation_percent: 0.0,
+            initial_direction: Some(direction::Direction::Right),
+            ..small_config()
+        };
+        for seed in 0..20 {
+            let genome = gene::random_genome(&mut rand::rngs::StdRng::seed_from_u64(seed), &config);
+            let bot = Bot::new_with_genome(3, 3, genome, &config);
+            let mut minimized_bot = bot;
+            minimized_bot.genome = gene::minimize_genome(&genome);
+            let simulation_with = |bot: Bot| {
+                Simulation::from_saved_state(SavedState {
+                    config,
+                    iterations: 0,
+                    cells: vec![bot],
+                    resources: Vec::new(),
+                })
+            };
+            let mut original = simulation_with(bot);
+            let mut minimized = simulation_with(minimized_bot);
+
+            for _ in 0..100 {
+                original.update();
+                minimized.update();
+                assert_eq!(trace(&original), trace(&minimized), "seed {seed}");
+            }
+        }
+    }
+
+    #[test]
+    fn to_ascii_draws_every_kind_of_cell() {
+        for sparse_map in [false, true] {
+            let config = Config {
+                width: 4,
+                height: 2,
+                sparse_map,
+                ..Default::default()
+            };
+            let reproduction_energy = config.reproduction_required_energy;
+            let genome = [Gene::default(); GENOME_LENGTH as usize];
+            let bot_with_energy = |x, y, energy| {
+                let mut bot = Bot::new_with_genome(x, y, genome, &config);
+                bot.energy = energy;
+                bot
+            };
+            let mut dead = bot_with_energy(0, 1, 0.0);
+            dead.alive = false;
+
+            let simulation = Simulation::from_saved_state(SavedState {
+                config,
+                iterations: 0,
+                cells: vec![
+                    bot_with_energy(0, 0, reproduction_energy / 2.),
+                    bot_with_energy(2, 0, reproduction_energy * 2.),
+                    bot_with_energy(3, 0, reproduction_energy * 10.),
+                    dead,
+                    Bot::new_wall(1, 1),
+                ],
+                resources: Vec::new(),
+            });
+
+            assert_eq!(
+                simulation.to_ascii(),
+                "o O@\n.#  \n",
+                "sparse: {sparse_map}"
+            );
+        }
+    }
+
+    #[test]
+    fn constant_population_stagnates_after_the_window() {
+        const WINDOW: usize = 50;
+        let config = Config {
+            mutation_percent: 0.0,
+            stagnation_window: Some(WINDOW),
+            ..small_config()
+        };
+        let mut genome = [Gene::default(); GENOME_LENGTH as usize];
+        for gene in genome.iter_mut() {
+            gene.instruction = Instruction::Photosynthesis;
+        }
+        // In the brightest row, where photosynthesis keeps them alive
+        let cells = (0..config.width)
+            .map(|x| Bot::new_with_genome(x, config.height - 1, genome, &config))
+            .collect();
+        let mut simulation = Simulation::from_saved_state(SavedState {
+            config,
+            iterations: 0,
+            cells,
+            resources: Vec::new(),
+        });
+
+        for _ in 0..WINDOW {
+            simulation.update();
+            assert!(!simulation.stats().stagnant);
+        }
+        simulation.update();
+        assert!(simulation.stats().stagnant);
+        assert_eq!(simulation.stats().alive_count, config.width);
+    }
+
+    #[test]
+    fn same_seed_gives_same_run() {
+        // Mutations, crossover and noisy sensing all draw from the generator
+        let config = Config {
+            crossover_mode: Some(config::CrossoverMode::Uniform),
+            sensing_noise: 1.0,
+            ..small_config()
+        };
+        let run = |seed| {
+            let mut simulation = Simulation::new_seeded(Some(config), seed);
+            for _ in 0..50 {
+                simulation.update();
+            }
+            simulation.inject_random(5);
+            simulation.reshuffle();
+            serde_json::to_string(&simulation.saved_state().cells).unwrap()
+        };
+
+        assert_eq!(run(1), run(1));
+        assert_ne!(run(1), run(2));
+    }
+
+    // Coordinates of the alive bots on a new 20x20 map seeded with the pattern
+    fn seeded_cells(pattern: config::SeedingPattern) -> Vec<(usize, usize)> {
+        let config = Config {
+            width: 20,
+            height: 20,
+            seeding_pattern: pattern,
+            ..Default::default()
+        };
+        let simulation = Simulation::new_seeded(Some(config), 0);
+        living_bots(simulation.map())
+            .map(|(x, y, _)| (x, y))
+            .collect()
+    }
+
+    #[test]
+    fn single_bot_is_seeded_in_the_center() {
+        assert_eq!(
+            seeded_cells(config::SeedingPattern::SingleBot),
+            vec![(10, 10)]
+        );
+    }
+
+    #[test]
+    fn checkerboard_seeds_every_other_cell() {
+        let cells = seeded_cells(config::SeedingPattern::Checkerboard);
+        assert_eq!(cells.len(), 200);
+        assert!(cells.iter().all(|&(x, y)| x % 2 == y % 2));
+    }
+
+    #[test]
+    fn corners_are_seeded_only_in_the_corners() {
+        // Corners are 5x5 squares on a 20x20 map
+        let in_corner = |coordinate: usize| !(5..15).contains(&coordinate);
+        let cells = seeded_cells(config::SeedingPattern::Corners);
+        assert!(!cells.is_empty());
+        assert!(cells.iter().all(|&(x, y)| in_corner(x) && in_corner(y)));
+        for (corner_x, corner_y) in [(0, 0), (15, 0), (0, 15), (15, 15)] {
+            assert!(cells
+                .iter()
+                .any(|&(x, y)| (corner_x..corner_x + 5).contains(&x)
+                    && (corner_y..corner_y + 5).contains(&y)));
+        }
+    }
+
+    #[test]
+    fn center_clump_is_denser_in_the_middle() {
+        let cells = seeded_cells(config::SeedingPattern::CenterClump);
+        let distance = |&(x, y): &(usize, usize)| {
+            ((x as f64 - 10.0).powi(2) + (y as f64 - 10.0).powi(2)).sqrt()
+        };
+        // Nothing spawns at the distance of the half-width
+        assert!(cells.iter().all(|cell| distance(cell) < 10.0));
+        // The inner disc of radius 5 has a third of the area of the ring around it,
+        // so it holds more bots per cell with about as many bots in total
+        let inner = cells.iter().filter(|cell| distance(cell) < 5.0).count();
+        assert!(
+            inner * 3 > (cells.len() - inner) * 2,
+            "{inner} of {}",
+            cells.len()
+        );
+    }
+
+    #[test]
+    fn uniform_random_seeds_a_fifth_of_the_map() {
+        let cells = seeded_cells(config::SeedingPattern::UniformRandom);
+        assert!((40..120).contains(&cells.len()), "{} bots", cells.len());
+        // Bots are spread over the whole map
+        for (left, top) in [(0, 0), (10, 0), (0, 10), (10, 10)] {
+            assert!(cells
+                .iter()
+                .any(|&(x, y)| (left..left + 10).contains(&x) && (top..top + 10).contains(&y)));
+        }
+    }
+
+    #[test]
+    fn surrounded_bot_dies_of_overcrowding() {
+        for (max_neighbors, survives) in [(Some(7), false), (Some(8), true), (None, true)] {
+            let config = Config {
+                overcrowd_death: max_neighbors,
+                ..small_config()
+            };
+            // A 3x3 block of idle bots, the one in the middle has 8 neighbors
+            let cells = (2..5)
+                .flat_map(|x| (2..5).map(move |y| (x, y)))
+                .map(|(x, y)| {
+                    Bot::new_with_genome(x, y, [Gene::default(); GENOME_LENGTH as usize], &config)
+                })
+                .collect();
+            let mut simulation = Simulation::from_saved_state(SavedState {
+                config,
+                iterations: 0,
+                cells,
+                resources: Vec::new(),
+            });
+
+            simulation.update();
+
+            assert_eq!(
+                simulation.map().get(3, 3).unwrap().alive,
+                survives,
+                "{max_neighbors:?}"
+            );
+            // The others have at most 5 neighbors
+            assert_eq!(simulation.stats().alive_count, 8 + survives as usize);
+        }
+    }
+
+    #[test]
+    fn injection_fills_at_most_the_empty_cells() {
+        let config = Config {
+            seeding_pattern: config::SeedingPattern::SingleBot,
+            ..small_config()
+        };
+        let mut simulation = Simulation::new_seeded(Some(config), 0);
+        assert_eq!(simulation.stats().alive_count, 1);
+
+        assert_eq!(simulation.inject_random(10), 10);
+        assert_eq!(simulation.stats().alive_count, 11);
+
+        // Only 37 of the 48 cells are left
+        assert_eq!(simulation.inject_random(100), 37);
+        assert_eq!(simulation.stats().alive_count, 48);
+        assert_eq!(simulation.inject_random(1), 0);
+    }
+
+    // Bots alive after one update of a map full of photosynthesizers, with given wrapping
+    fn kill_zone_survivors(wrap_x: bool, wrap_y: bool) -> Vec<(usize, usize)> {
+        let config = Config {
+            edge_kill_margin: 1,
+            wrap_x,
+            wrap_y,
+            ..small_config()
+        };
+        let genome = [Gene {
+            instruction: Instruction::Photosynthesis,
+            ..Default::default()
+        }; GENOME_LENGTH as usize];
+        let cells = (0..config.width)
+            .flat_map(|x| (0..config.height).map(move |y| (x, y)))
+            .map(|(x, y)| Bot::new_with_genome(x, y, genome, &config))
+            .collect();
+        let mut simulation = Simulation::from_saved_state(SavedState {
+            config,
+            iterations: 0,
+            cells,
+            resources: Vec::new(),
+        });
+
+        simulation.update();
+
+        let mut survivors: Vec<_> = simulation.living_bots().map(|(x, y, _)| (x, y)).collect();
+        survivors.sort();
+        survivors
+    }
+
+    #[test]
+    fn bots_in_the_kill_zone_die() {
+        let inside = |xs: std::ops::Range<usize>, ys: std::ops::Range<usize>| {
+            xs.flat_map(|x| ys.clone().map(move |y| (x, y)))
+                .collect::<Vec<_>>()
+        };
+        // The map is 8x6
+        assert_eq!(kill_zone_survivors(false, false), inside(1..7, 1..5));
+        assert_eq!(kill_zone_survivors(true, false), inside(0..8, 1..5));
+        assert_eq!(kill_zone_survivors(false, true), inside(1..7, 0..6));
+        assert_eq!(kill_zone_survivors(true, true), inside(0..8, 0..6));
+    }
+
+    #[test]
+    fn photosynthesizer_depletes_its_cell() {
+        let config = Config {
+            resource_cap: Some(2.0),
+            resource_regrowth: 0.25,
+            ..small_config()
+        };
+        let genome = [Gene {
+            instruction: Instruction::Photosynthesis,
+            ..Default::default()
+        }; GENOME_LENGTH as usize];
+        // On the bottom row of the 6 rows, the full yield is 5/6 of the photosynthesis energy
+        let mut simulation = Simulation::from_saved_state(SavedState {
+            config,
+            iterations: 0,
+            cells: vec![Bot::new_with_genome(3, 5, genome, &config)],
+            resources: Vec::new(),
+        });
+
+        let mut gains = Vec::new();
+        for _ in 0..8 {
+            let before = simulation.map().get(3, 5).unwrap().energy;
+            simulation.update();
+            let after = simulation.map().get(3, 5).unwrap().energy;
+            gains.push(after - before + config.noop_cost);
+        }
+
+        assert!((gains[0] - 5.0 / 6.0).abs() < 1e-4, "{gains:?}");
+        assert!(
+            gains.windows(2).all(|pair| pair[1] <= pair[0] + 1e-4),
+            "{gains:?}"
+        );
+        // Once depleted, the bot only gets what regrows
+        assert!((gains[7] - 0.25).abs() < 1e-4, "{gains:?}");
+    }
+
+    #[test]
+    fn reshuffle_keeps_genomes_and_moves_bots() {
+        let mut simulation = Simulation::new_seeded(Some(small_config()), 0);
+        for _ in 0..5 {
+            simulation.update();
+        }
+        // Genomes sorted by their serialized form, and coordinates of the alive bots
+        let population = |simulation: &Simulation| {
+            let mut genomes: Vec<String> = simulation
+                .living_bots()
+                .map(|(_, _, bot)| serde_json::to_string(&bot.genome).unwrap())
+                .collect();
+            genomes.sort();
+            let cells: Vec<_> = simulation.living_bots().map(|(x, y, _)| (x, y)).collect();
+            (genomes, cells)
+        };
+        let (genomes_before, cells_before) = population(&simulation);
+        assert!(genomes_before.len() > 1);
+
+        simulation.reshuffle();
+
+        let (genomes_after, cells_after) = population(&simulation);
+        assert_eq!(genomes_after, genomes_before);
+        assert_ne!(cells_after, cells_before);
+        let config = simulation.configuration;
+        assert!(simulation
+            .living_bots()
+            .all(|(_, _, bot)| bot.energy == config.start_energy && bot.age == 0));
+    }
+
+    #[test]
+    fn injected_genome_appears_as_many_times_as_asked() {
+        let config = Config {
+            seeding_pattern: config::SeedingPattern::SingleBot,
+            ..small_config()
+        };
+        let mut simulation = Simulation::new_seeded(Some(config), 0);
+        let mut genome = [Gene::default(); GENOME_LENGTH as usize];
+        genome[0].instruction = Instruction::AttackCell;
+        genome[1].instruction = Instruction::MoveForwards;
+        genome[1].branch = 7;
+
+        assert_eq!(simulation.inject_genome(genome, 12), 12);
+
+        let injected: Vec<_> = simulation
+            .living_bots()
+            .filter(|(_, _, bot)| bot.genome == genome)
+            .map(|(_, _, bot)| bot)
+            .collect();
+        assert_eq!(injected.len(), 12);
+        assert!(injected
+            .iter()
+            .all(|bot| bot.lineage == injected[0].lineage));
+        assert_eq!(simulation.stats().alive_count, 13);
+    }
+
+    #[test]
+    fn dying_bot_feeds_its_neighbors() {
+        let config = Config {
+            death_energy_to_neighbors: true,
+            overcrowd_death: Some(3),
+            ..small_config()
+        };
+        // The bot in the middle has 4 neighbors, so it dies of overcrowding.
+        // Each of the neighbors has only 3
+        let genome = [Gene::default(); GENOME_LENGTH as usize];
+        let neighbors = [(3, 2), (2, 3), (4, 3), (3, 4)];
+        let mut cells: Vec<Bot> = neighbors
+            .iter()
+            .map(|&(x, y)| Bot::new_with_genome(x, y, genome, &config))
+            .collect();
+        for bot in cells.iter_mut() {
+            bot.energy = 20.0;
+        }
+        let mut dying = Bot::new_with_genome(3, 3, genome, &config);
+        dying.energy = 40.0;
+        cells.push(dying);
+        let mut simulation = Simulation::from_saved_state(SavedState {
+            config,
+            iterations: 0,
+            cells,
+            resources: Vec::new(),
+        });
+
+        simulation.update();
+
+        let corpse = simulation.map().get(3, 3).unwrap();
+        assert!(!corpse.alive);
+        assert_eq!(corpse.energy, 0.0);
+        for (x, y) in neighbors {
+            let energy = simulation.map().get(x, y).unwrap().energy;
+            assert!(
+                (energy - (20.0 + 10.0 - config.noop_cost)).abs() < 1e-4,
+                "{energy}"
+            );
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::fs;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::bot::Bot;
+use crate::error::{Error, Result};
+use crate::Config;
+
+/// Version of the save format.
+/// Must be bumped whenever the serialized shape of [`Config`] or [`Bot`] changes,
+/// so that old saves are rejected instead of being loaded incorrectly
+pub const SAVE_VERSION: u32 = 27;
+
+#[derive(Serialize, Deserialize)]
+struct SaveFile<T> {
+    version: u32,
+    data: T,
+}
+
+// Only the version is parsed first, so that a mismatch is reported
+// instead of an error about the changed layout
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+/// Full state of a simulation, see [`Simulation::saved_state`](super::Simulation::saved_state)
+#[derive(Serialize, Deserialize)]
+pub struct SavedState {
+    pub config: Config,
+    pub iterations: usize,
+    /// All non-empty cells, each one knows it's coordinates
+    pub cells: Vec<Bot>,
+    /// Resource left on each cell, see [`Config::resource_cap`].
+    /// Empty if resources were never used, then every cell starts full
+    #[serde(default)]
+    pub resources: Vec<f32>,
+}
+
+/// Write anything serializable (e.g. [`Config`] or [`SavedState`]) to a file, tagged with [`SAVE_VERSION`]
+pub fn save_to_path<T: Serialize>(path: impl AsRef<Path>, data: &T) -> Result<()> {
+    let file = SaveFile {
+        version: SAVE_VERSION,
+        data,
+    };
+    let json = serde_json::to_string(&file)?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+/// Read a file written by [`save_to_path`].
+/// Fails if the file was saved with a different [`SAVE_VERSION`]
+pub fn load_from_path<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T> {
+    let json = fs::read_to_string(path)?;
+
+    let header: SaveHeader = serde_json::from_str(&json)?;
+    if header.version != SAVE_VERSION {
+        return Err(Error::SaveVersion(header.version));
+    }
+
+    let file: SaveFile<T> = serde_json::from_str(&json)?;
+    Ok(file.data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    // File in the temporary directory, unique to this test run
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("cell-simulation-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn older_save_is_rejected() {
+        let path = temp_path("older_save.json");
+        let file = SaveFile {
+            version: SAVE_VERSION - 1,
+            data: Config::default(),
+        };
+        fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+
+        let loaded = load_from_path::<Config>(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(loaded, Err(Error::SaveVersion(version)) if version == SAVE_VERSION - 1));
+    }
+
+    #[test]
+    fn matching_save_round_trips() {
+        let path = temp_path("matching_save.json");
+        let config = Config {
+            width: 12,
+            mutation_percent: 3.0,
+            ..Default::default()
+        };
+
+        save_to_path(&path, &config).unwrap();
+        let loaded = load_from_path::<Config>(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(loaded.unwrap() == config);
+    }
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        let loaded = load_from_path::<Config>(temp_path("missing.json"));
+        assert!(matches!(loaded, Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn malformed_file_is_a_serde_error() {
+        let path = temp_path("malformed.json");
+        fs::write(&path, "{ not json").unwrap();
+
+        let loaded = load_from_path::<Config>(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(loaded, Err(Error::Serde(_))));
+    }
+}
@@ -0,0 +1,407 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::bot::Bot;
+use super::gene::Instruction;
+use super::map::Map;
+use super::save::SavedState;
+use crate::Config;
+
+/// What counts as one species when computing [`Stats::species_count`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SpeciesDefinition {
+    /// Bots with exactly the same sequence of instructions
+    IdenticalInstructions,
+    /// Bots whose instructions differ in at most this many genes.
+    /// Bots are grouped greedily: each bot joins the first species whose
+    /// founder is close enough, or founds a new one
+    SimilarityRadius(u8),
+}
+
+/// How many times each kind of action was successfully taken during one iteration
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ActionTally {
+    pub moved: usize,
+    pub photosynthesized: usize,
+    pub gave_energy: usize,
+    pub attacked: usize,
+    pub recycled: usize,
+    pub reproduced: usize,
+}
+
+/// Statistics about the population, recomputed after each iteration
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats {
+    pub alive_count: usize,
+    pub dead_count: usize,
+    pub total_energy: f32,
+    pub species_count: usize,
+    // Average `gene::complexity` of alive bots. Reachability is expensive to compute,
+    // so this is filled in by the runner every few updates, and is 0 elsewhere
+    pub average_complexity: f32,
+    // Actions taken during the last iteration. Filled in by the simulation
+    pub actions: ActionTally,
+    // Whether evolution has stopped, see `StagnationDetector`. Filled in by the simulation
+    pub stagnant: bool,
+}
+
+/// The population is sampled for [`StagnationDetector`] once per this many iterations,
+/// since computing the histograms on every update would be too slow
+const STAGNATION_SAMPLE_INTERVAL: usize = 10;
+
+/// Flags evolutionary equilibrium, see [`Config::stagnation_window`].
+/// Samples of the population are compared to a reference sample,
+/// which is replaced whenever one of them differs by more than [`Config::stagnation_threshold`]
+#[derive(Debug, Clone, Default)]
+pub struct StagnationDetector {
+    // Share of the dominant species, share of each instruction among the genes,
+    // and the iteration the reference sample was taken on
+    reference: Option<(f32, [f32; Instruction::COUNT], usize)>,
+    stagnant: bool,
+    next_sample: usize,
+}
+
+impl StagnationDetector {
+    /// Compare a sample of the population to the reference.
+    /// Stagnant once the reference has held for `window` iterations
+    pub fn observe(
+        &mut self,
+        dominant_share: f32,
+        instruction_shares: [f32; Instruction::COUNT],
+        iteration: usize,
+        config: &Config,
+    ) {
+        let threshold = config.stagnation_threshold;
+        let changed = match &self.reference {
+            Some((share, shares, _)) => {
+                (share - dominant_share).abs() > threshold
+                    || shares
+                        .iter()
+                        .zip(instruction_shares.iter())
+                        .any(|(old, new)| (old - new).abs() > threshold)
+            }
+            None => true,
+        };
+        if changed {
+            self.reference = Some((dominant_share, instruction_shares, iteration));
+        }
+        self.next_sample = iteration + STAGNATION_SAMPLE_INTERVAL;
+
+        self.stagnant = match (self.reference, config.stagnation_window) {
+            (Some((_, _, since)), Some(window)) => iteration.saturating_sub(since) >= window,
+            _ => false,
+        };
+    }
+
+    pub fn is_stagnant(&self) -> bool {
+        self.stagnant
+    }
+
+    /// Iteration on which the population should be sampled next
+    pub fn next_sample(&self) -> usize {
+        self.next_sample
+    }
+
+    /// Forget the reference, for when the population is replaced or detection is disabled
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl Stats {
+    pub fn compute(map: &Map<Bot>, config: &Config) -> Self {
+        let mut stats = Stats::default();
+
+        let mut fingerprints = HashSet::new();
+        let mut founders: Vec<&Bot> = Vec::new();
+
+        for (_, _, bot) in map.iter() {
+            if bot.is_dead() {
+                stats.dead_count += 1;
+            }
+            if !bot.alive || bot.is_tail() {
+                continue;
+            }
+
+            stats.alive_count += 1;
+            stats.total_energy += bot.energy;
+
+            match config.species_definition {
+                SpeciesDefinition::IdenticalInstructions => {
+                    fingerprints.insert(bot.instruction_fingerprint());
+                }
+                SpeciesDefinition::SimilarityRadius(radius) => {
+                    let is_known = founders
+                        .iter()
+                        .any(|founder| differing_instructions(founder, bot) <= radius as usize);
+                    if !is_known {
+                        founders.push(bot);
+                    }
+                }
+            }
+        }
+
+        stats.species_count = fingerprints.len() + founders.len();
+        stats
+    }
+}
+
+// Number of genes with different instructions
+fn differing_instructions(a: &Bot, b: &Bot) -> usize {
+    a.genome
+        .iter()
+        .zip(b.genome.iter())
+        .filter(|(ours, theirs)| ours.instruction != theirs.instruction)
+        .count()
+}
+
+/// How the population changed between two snapshots, see [`Simulation::compare_snapshots`].
+/// Species are compared by identical instructions, regardless of the config
+///
+/// [`Simulation::compare_snapshots`]: super::Simulation::compare_snapshots
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EvolutionReport {
+    pub iterations: usize,
+    /// Change of the share of each instruction among the genes of all alive bots,
+    /// from -1 to 1. Indexed like [`Instruction::ALL`]
+    pub instruction_changes: [f32; Instruction::COUNT],
+    /// Change of the average energy of alive bots
+    pub average_energy_change: f32,
+    pub species_before: usize,
+    pub species_after: usize,
+    /// Species alive in both snapshots
+    pub surviving_species: usize,
+}
+
+impl EvolutionReport {
+    pub fn compare(before: &SavedState, after: &SavedState) -> Self {
+        let (shares_before, energy_before, species_before) = summarize(before);
+        let (shares_after, energy_after, species_after) = summarize(after);
+
+        let mut instruction_changes = [0.0; Instruction::COUNT];
+        for (i, change) in instruction_changes.iter_mut().enumerate() {
+            *change = shares_after[i] - shares_before[i];
+        }
+
+        EvolutionReport {
+            iterations: after.iterations.saturating_sub(before.iterations),
+            instruction_changes,
+            average_energy_change: energy_after - energy_before,
+            species_before: species_before.len(),
+            species_after: species_after.len(),
+            surviving_species: species_before.intersection(&species_after).count(),
+        }
+    }
+}
+
+impl fmt::Display for EvolutionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "After {} iterations:", self.iterations)?;
+        writeln!(f, "Average energy: {:+.2}", self.average_energy_change)?;
+        writeln!(
+            f,
+            "Species: {} -> {} ({} survived, {} went extinct, {} appeared)",
+            self.species_before,
+            self.species_after,
+            self.surviving_species,
+            self.species_before - self.surviving_species,
+            self.species_after - self.surviving_species
+        )?;
+
+        // Biggest changes first, unchanged instructions are left out
+        let mut changes: Vec<(Instruction, f32)> = Instruction::ALL
+            .into_iter()
+            .zip(self.instruction_changes)
+            .filter(|&(_, change)| change != 0.0)
+            .collect();
+        changes.sort_by(|a, b| b.1.abs().total_cmp(&a.1.abs()));
+        for (instruction, change) in changes {
+            writeln!(f, "{instruction:?}: {:+.2}%", change * 100.)?;
+        }
+        Ok(())
+    }
+}
+
+// Share of each instruction among the genes, average energy,
+// and instruction fingerprints of all alive bots in a snapshot
+fn summarize(state: &SavedState) -> ([f32; Instruction::COUNT], f32, HashSet<u64>) {
+    let mut shares = [0.0; Instruction::COUNT];
+    let mut total_energy = 0.0;
+    let mut species = HashSet::new();
+
+    let alive: Vec<&Bot> = state
+        .cells
+        .iter()
+        .filter(|bot| bot.alive && !bot.is_tail())
+        .collect();
+    for bot in alive.iter() {
+        for gene in bot.genome.iter() {
+            shares[gene.instruction as usize] += 1.0;
+        }
+        total_energy += bot.energy;
+        species.insert(bot.instruction_fingerprint());
+    }
+
+    if alive.is_empty() {
+        return (shares, 0.0, species);
+    }
+    let gene_count: f32 = shares.iter().sum();
+    for share in shares.iter_mut() {
+        *share /= gene_count;
+    }
+    (shares, total_energy / alive.len() as f32, species)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::config::GENOME_LENGTH;
+    use crate::simulation::gene::Gene;
+
+    #[test]
+    fn report_compares_instructions_energy_and_species() {
+        let config = Config::default();
+        let bot = |instruction, energy| {
+            let genome = [Gene {
+                instruction,
+                ..Default::default()
+            }; GENOME_LENGTH as usize];
+            let mut bot = Bot::new_with_genome(0, 0, genome, &config);
+            bot.energy = energy;
+            bot
+        };
+        let mut dead = bot(Instruction::Photosynthesis, 100.0);
+        dead.alive = false;
+
+        let before = SavedState {
+            config,
+            iterations: 100,
+            cells: vec![bot(Instruction::Noop, 10.0), bot(Instruction::Noop, 20.0)],
+            resources: Vec::new(),
+        };
+        let after = SavedState {
+            config,
+            iterations: 250,
+            cells: vec![
+                bot(Instruction::Noop, 10.0),
+                bot(Instruction::TurnLeft, 30.0),
+                dead,
+            ],
+            resources: Vec::new(),
+        };
+
+        let report = EvolutionReport::compare(&before, &after);
+
+        let mut instruction_changes = [0.0; Instruction::COUNT];
+        instruction_changes[Instruction::Noop as usize] = -0.5;
+        instruction_changes[Instruction::TurnLeft as usize] = 0.5;
+        assert_eq!(
+            report,
+            EvolutionReport {
+                iterations: 150,
+                instruction_changes,
+                average_energy_change: 5.0,
+                species_before: 1,
+                species_after: 2,
+                surviving_species: 1,
+            }
+        );
+    }
+
+    fn stagnation_config(window: Option<usize>) -> Config {
+        Config {
+            stagnation_window: window,
+            stagnation_threshold: 0.1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn stagnant_once_the_reference_holds_for_the_window() {
+        let config = stagnation_config(Some(100));
+        let shares = [0.0; Instruction::COUNT];
+        let mut detector = StagnationDetector::default();
+
+        detector.observe(0.5, shares, 0, &config);
+        assert!(!detector.is_stagnant());
+        assert_eq!(detector.next_sample(), STAGNATION_SAMPLE_INTERVAL);
+        // Changes within the threshold keep the reference
+        detector.observe(0.55, shares, 50, &config);
+        assert!(!detector.is_stagnant());
+        detector.observe(0.45, shares, 100, &config);
+        assert!(detector.is_stagnant());
+    }
+
+    #[test]
+    fn big_change_replaces_the_reference() {
+        let config = stagnation_config(Some(100));
+        let shares = [0.0; Instruction::COUNT];
+        let mut detector = StagnationDetector::default();
+        detector.observe(0.5, shares, 0, &config);
+
+        let mut changed_shares = shares;
+        changed_shares[Instruction::TurnLeft as usize] = 0.5;
+        detector.observe(0.5, changed_shares, 100, &config);
+        assert!(!detector.is_stagnant());
+
+        detector.observe(0.9, changed_shares, 200, &config);
+        assert!(!detector.is_stagnant());
+        detector.observe(0.9, changed_shares, 300, &config);
+        assert!(detector.is_stagnant());
+    }
+
+    #[test]
+    fn never_stagnant_without_a_window() {
+        let config = stagnation_config(None);
+        let shares = [0.0; Instruction::COUNT];
+        let mut detector = StagnationDetector::default();
+
+        for iteration in [0, 1000, 100_000] {
+            detector.observe(0.5, shares, iteration, &config);
+            assert!(!detector.is_stagnant());
+        }
+    }
+
+    // Two bots each of two genomes, and one bot differing from the second genome by one gene
+    fn two_species_and_a_mutant(config: &Config) -> Map<Bot> {
+        let first = [Gene::default(); GENOME_LENGTH as usize];
+        let second = [Gene {
+            instruction: Instruction::Photosynthesis,
+            ..Default::default()
+        }; GENOME_LENGTH as usize];
+        let mut mutant = second;
+        mutant[3].instruction = Instruction::MoveForwards;
+
+        let mut map = Map::new(5, 1);
+        for (x, genome) in [first, first, second, second, mutant]
+            .into_iter()
+            .enumerate()
+        {
+            map.set(x, 0, Bot::new_with_genome(x, 0, genome, config));
+        }
+        map
+    }
+
+    #[test]
+    fn species_are_distinct_instruction_sequences() {
+        let config = Config::default();
+        let mut map = two_species_and_a_mutant(&config);
+        map.set(4, 0, Bot::new_empty(4, 0));
+        assert_eq!(Stats::compute(&map, &config).species_count, 2);
+
+        let map = two_species_and_a_mutant(&config);
+        assert_eq!(Stats::compute(&map, &config).species_count, 3);
+    }
+
+    #[test]
+    fn species_within_the_radius_are_merged() {
+        let config = Config {
+            species_definition: SpeciesDefinition::SimilarityRadius(1),
+            ..Default::default()
+        };
+        let map = two_species_and_a_mutant(&config);
+        assert_eq!(Stats::compute(&map, &config).species_count, 2);
+    }
+}
@@ -0,0 +1,55 @@
+//! Runs a seeded simulation and compares a hash of the final map to a committed value,
+//! so that any change of the model's behavior is noticed.
+//! If the change is intended, regenerate the value with
+//! `cargo test --test golden -- --ignored --nocapture` and update `GOLDEN_HASH`
+
+use cell_simulation::simulation::{config::CrossoverMode, Simulation};
+use cell_simulation::Config;
+
+const GOLDEN_HASH: u64 = 0x94ddb2442d3368a5;
+
+const SEED: u64 = 196;
+const TICKS: usize = 200;
+
+// Hash of the map after running the seeded simulation
+fn run_hash() -> u64 {
+    let config = Config {
+        width: 40,
+        height: 30,
+        // Mutations, crossover and noisy sensing all draw from the seeded generator
+        crossover_mode: Some(CrossoverMode::Uniform),
+        sensing_noise: 1.0,
+        ..Default::default()
+    };
+    let mut simulation = Simulation::new_seeded(Some(config), SEED);
+    for _ in 0..TICKS {
+        simulation.update();
+    }
+    // A population that died out would hide most changes
+    assert!(simulation.stats().alive_count > 0);
+
+    let cells = serde_json::to_string(&simulation.saved_state().cells).unwrap();
+    fnv1a(cells.as_bytes())
+}
+
+// `DefaultHasher` may change between Rust versions, this hash never does
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[test]
+fn seeded_run_matches_golden_hash() {
+    let hash = run_hash();
+    assert_eq!(
+        hash, GOLDEN_HASH,
+        "the simulation behaves differently, see the comment at the top of the file"
+    );
+}
+
+#[test]
+#[ignore = "prints the hash to put into GOLDEN_HASH"]
+fn regenerate_golden_hash() {
+    println!("const GOLDEN_HASH: u64 = {:#018x};", run_hash());
+}